@@ -25,17 +25,21 @@ use druid_shell::piet::{
 };
 
 use druid_shell::{
-    keyboard_types::Key, Application, Cursor, FileDialogOptions, FileDialogToken, FileInfo,
-    FileSpec, HotKey, KeyEvent, Menu, MouseEvent, Region, SysMods, TextInputHandler,
-    TextInputToken, TextInputUpdate, TimerToken, WinHandler, WindowBuilder, WindowHandle,
+    keyboard_types::Key, Affinity, Application, CompositionStyle, Cursor, EditHistory, EditKind,
+    FileDialogOptions, FileDialogToken, FileInfo, FileSpec, HotKey, KeyEvent, Menu, MouseButton,
+    MouseEvent, Region, SelectionGesture, SysMods, TextInputHandler, TextInputToken,
+    TextInputUpdate, TimerToken, WinHandler, WindowBuilder, WindowHandle,
 };
 
 use druid_shell::kurbo::{Point, Rect};
 
 const BG_COLOR: Color = Color::rgb8(0xff, 0xff, 0xff);
 const COMPOSITION_BG_COLOR: Color = Color::rgb8(0xff, 0xd8, 0x6e);
+const SELECTED_CLAUSE_BG_COLOR: Color = Color::rgb8(0xff, 0xbd, 0x2e);
 const SELECTION_BG_COLOR: Color = Color::rgb8(0x87, 0xc5, 0xff);
 const CARET_COLOR: Color = Color::rgb8(0x00, 0x82, 0xfc);
+const CLAUSE_UNDERLINE_COLOR: Color = Color::rgb8(0x4a, 0x3b, 0x00);
+const UNCONVERTED_UNDERLINE_COLOR: Color = Color::rgb8(0xb0, 0x96, 0x4f);
 // const FG_COLOR: Color = Color::rgb8(0xf0, 0xf0, 0xea);
 const FONT: FontFamily = FontFamily::SANS_SERIF;
 const FONT_SIZE: f64 = 16.0;
@@ -46,6 +50,7 @@ struct AppState {
     handle: WindowHandle,
     document: Rc<RefCell<DocumentState>>,
     text_input_token: Option<TextInputToken>,
+    selection_gesture: SelectionGesture,
 }
 
 #[derive(Default)]
@@ -53,8 +58,16 @@ struct DocumentState {
     text: String,
     selection: Range<usize>,
     composition: Option<Range<usize>>,
+    /// The input method's clause segmentation of `composition`, as reported through
+    /// `TextInputHandler::set_composition_styling`. Empty if the input method hasn't reported
+    /// any (or there's no composition in progress), in which case `composition` is drawn as one
+    /// flat region instead.
+    composition_clauses: Vec<(Range<usize>, CompositionStyle)>,
     text_engine: Option<PietText>,
     layout: Option<PietTextLayout>,
+    /// Undo/redo history for `replace_range` edits, giving this text box Ctrl+Z/Ctrl+Shift+Z
+    /// behavior via `AppTextInputHandler::undo`/`redo`.
+    history: EditHistory,
 }
 
 impl DocumentState {
@@ -86,38 +99,70 @@ impl WinHandler for AppState {
     }
 
     fn paint(&mut self, piet: &mut piet_common::Piet, _: &Region) {
-        // TODO bidi
         let rect = self.size.to_rect();
         piet.fill(rect, &BG_COLOR);
-        let doc = self.document.borrow();
-        let layout = doc.layout.as_ref().unwrap();
-        if let Some(composition_range) = doc.composition.as_ref() {
-            let left_x = layout
-                .hit_test_text_position(composition_range.start)
-                .point
-                .x;
-            let right_x = layout.hit_test_text_position(composition_range.end).point.x;
-            piet.fill(
-                Rect::new(left_x, 0.0, right_x, FONT_SIZE),
-                &COMPOSITION_BG_COLOR,
-            );
+
+        let (composition_range, composition_clauses, selection) = {
+            let doc = self.document.borrow();
+            (
+                doc.composition.clone(),
+                doc.composition_clauses.clone(),
+                doc.selection.clone(),
+            )
+        };
+        // Bidirectional text can map one logical range onto several visual rects (for instance, a
+        // range that crosses out of a right-to-left run into the left-to-right text around it),
+        // so every highlight below iterates whatever `slice_bounding_box` returns rather than
+        // assuming a single rect.
+        let mut input_handler = self.text_input(self.text_input_token.unwrap(), false).unwrap();
+
+        if let Some(composition_range) = composition_range {
+            if composition_clauses.is_empty() {
+                for r in input_handler.slice_bounding_box(composition_range) {
+                    piet.fill(r, &COMPOSITION_BG_COLOR);
+                }
+            } else {
+                for (clause, style) in &composition_clauses {
+                    let (bg_color, underline_color, underline_width) = match style {
+                        CompositionStyle::Selected => {
+                            (SELECTED_CLAUSE_BG_COLOR, CLAUSE_UNDERLINE_COLOR, 3.0)
+                        }
+                        CompositionStyle::Converted => {
+                            (COMPOSITION_BG_COLOR, CLAUSE_UNDERLINE_COLOR, 1.0)
+                        }
+                        CompositionStyle::Unconverted => {
+                            (COMPOSITION_BG_COLOR, UNCONVERTED_UNDERLINE_COLOR, 1.0)
+                        }
+                    };
+                    for r in input_handler.slice_bounding_box(clause.clone()) {
+                        piet.fill(r, &bg_color);
+                        let underline_y = FONT_SIZE - 1.0;
+                        piet.stroke(
+                            Line::new((r.x0, underline_y), (r.x1, underline_y)),
+                            &underline_color,
+                            underline_width,
+                        );
+                    }
+                }
+            }
         }
-        if doc.selection.start != doc.selection.end {
-            let left_x = layout.hit_test_text_position(doc.selection.start).point.x;
-            let right_x = layout.hit_test_text_position(doc.selection.end).point.x;
-            piet.fill(
-                Rect::new(left_x, 0.0, right_x, FONT_SIZE),
-                &SELECTION_BG_COLOR,
-            );
+        if selection.start != selection.end {
+            for r in input_handler.slice_bounding_box(selection.clone()) {
+                piet.fill(r, &SELECTION_BG_COLOR);
+            }
         }
-        piet.draw_text(layout, (0.0, 0.0));
+
+        let doc = self.document.borrow();
+        piet.draw_text(doc.layout.as_ref().unwrap(), (0.0, 0.0));
+        drop(doc);
 
         // draw caret
-        let caret_x = layout.hit_test_text_position(doc.selection.end).point.x;
-        piet.fill(
-            Rect::new(caret_x - 1.0, 0.0, caret_x + 1.0, FONT_SIZE),
-            &CARET_COLOR,
-        );
+        for r in input_handler.slice_bounding_box(selection.end..selection.end) {
+            piet.fill(
+                Rect::new(r.x0 - 1.0, 0.0, r.x0 + 1.0, FONT_SIZE),
+                &CARET_COLOR,
+            );
+        }
     }
 
     fn command(&mut self, id: u32) {
@@ -150,9 +195,72 @@ impl WinHandler for AppState {
             // return true prevents the keypress event from being handled as text input
             return true;
         }
+        if event.key == Key::Character("p".to_string()) {
+            // stand-in hotkey for this example; a real app would bind this to the platform's
+            // usual character-palette shortcut (or an Edit menu item) instead.
+            self.handle
+                .show_character_palette(self.text_input_token.unwrap());
+            return true;
+        }
         false
     }
 
+    fn mouse_down(&mut self, event: &MouseEvent) {
+        let token = match self.text_input_token {
+            Some(token) => token,
+            None => return,
+        };
+        let mut input_handler = match self.text_input(token, true) {
+            Some(input_handler) => input_handler,
+            None => return,
+        };
+        if event.mods.shift() {
+            self.selection_gesture
+                .extend_from_selection(input_handler.as_mut(), event.pos);
+        } else {
+            self.selection_gesture
+                .mouse_down(input_handler.as_mut(), event.pos, event.count);
+        }
+        self.handle
+            .update_text_input(token, TextInputUpdate::SelectionChanged);
+        self.handle.request_anim_frame();
+    }
+
+    fn mouse_move(&mut self, event: &MouseEvent) {
+        if !event.buttons.contains(MouseButton::Left) {
+            return;
+        }
+        let token = match self.text_input_token {
+            Some(token) => token,
+            None => return,
+        };
+        let mut input_handler = match self.text_input(token, true) {
+            Some(input_handler) => input_handler,
+            None => return,
+        };
+        self.selection_gesture
+            .extend(input_handler.as_mut(), event.pos);
+        self.handle
+            .update_text_input(token, TextInputUpdate::SelectionChanged);
+        self.handle.request_anim_frame();
+    }
+
+    fn mouse_up(&mut self, event: &MouseEvent) {
+        let token = match self.text_input_token {
+            Some(token) => token,
+            None => return,
+        };
+        let mut input_handler = match self.text_input(token, true) {
+            Some(input_handler) => input_handler,
+            None => return,
+        };
+        self.selection_gesture
+            .extend(input_handler.as_mut(), event.pos);
+        self.handle
+            .update_text_input(token, TextInputUpdate::SelectionChanged);
+        self.handle.request_anim_frame();
+    }
+
     fn text_input(
         &mut self,
         _token: TextInputToken,
@@ -201,15 +309,65 @@ impl TextInputHandler for AppTextInputHandler {
         self.window_handle.request_anim_frame();
     }
     fn set_composition_range(&mut self, range: Option<Range<usize>>) {
-        self.state.borrow_mut().composition = range;
+        let mut doc = self.state.borrow_mut();
+        doc.composition = range;
+        if doc.composition.is_none() {
+            doc.composition_clauses.clear();
+        }
         self.window_handle.request_anim_frame();
     }
-    fn replace_range(&mut self, range: Range<usize>, text: &str) {
-        let mut doc = self.state.borrow_mut();
-        doc.text.replace_range(range, text);
-        doc.refresh_layout();
+    fn set_composition_styling(&mut self, ranges: &[(Range<usize>, CompositionStyle)]) {
+        self.state.borrow_mut().composition_clauses = ranges.to_vec();
         self.window_handle.request_anim_frame();
     }
+    fn replace_range(&mut self, range: Range<usize>, text: &str) {
+        {
+            let mut doc = self.state.borrow_mut();
+            let removed = doc.text[range.clone()].to_string();
+            // A single-character replacement at the caret is ordinary typing or backspacing, and
+            // coalesces with its neighbors into one undo step; anything bigger (a pasted or
+            // IME-committed string, a selection replacement, a word/line kill) is its own step.
+            let at_caret = doc.selection.start == doc.selection.end;
+            let kind = if at_caret && removed.chars().count() <= 1 && text.chars().count() <= 1 {
+                EditKind::Typing
+            } else {
+                EditKind::Discrete
+            };
+            let selection_before = doc.selection.clone();
+            let selection_after = range.start..range.start + text.len();
+            doc.history.record(
+                kind,
+                range.clone(),
+                removed,
+                text.to_string(),
+                selection_before,
+                selection_after,
+            );
+        }
+        self.apply_edit(range, text);
+    }
+    fn undo(&mut self) -> bool {
+        let undone = self.state.borrow_mut().history.undo();
+        match undone {
+            Some((range, text, selection)) => {
+                self.apply_edit(range, &text);
+                self.set_selected_range(selection);
+                true
+            }
+            None => false,
+        }
+    }
+    fn redo(&mut self) -> bool {
+        let redone = self.state.borrow_mut().history.redo();
+        match redone {
+            Some((range, text, selection)) => {
+                self.apply_edit(range, &text);
+                self.set_selected_range(selection);
+                true
+            }
+            None => false,
+        }
+    }
     fn slice<'a>(&'a mut self, range: Range<usize>) -> Cow<'a, str> {
         self.state.borrow().text[range].to_string().into()
     }
@@ -235,19 +393,76 @@ impl TextInputHandler for AppTextInputHandler {
             self.window_size.height,
         ))
     }
-    fn slice_bounding_box(&mut self, range: Range<usize>) -> Option<Rect> {
-        let doc = self.state.borrow();
-        let layout = doc.layout.as_ref().unwrap();
-        let range_start_x = layout.hit_test_text_position(range.start).point.x;
-        let range_end_x = layout.hit_test_text_position(range.end).point.x;
-        Some(Rect::new(range_start_x, 0.0, range_end_x, FONT_SIZE))
+    fn slice_bounding_box(&mut self, range: Range<usize>) -> Vec<Rect> {
+        if range.start == range.end {
+            let x = self.hit_test_x(range.start);
+            return vec![Rect::new(x, 0.0, x, FONT_SIZE)];
+        }
+        // Walk the range grapheme by grapheme and start a new rect wherever the hit-tested x
+        // position moves backward, i.e. wherever the range crosses from one visual run into
+        // another. `piet_common::PietTextLayout` doesn't do bidirectional layout, so in practice
+        // this example never splits, but a layout backend that did would fall out of this the
+        // same way.
+        let mut rects = Vec::new();
+        let mut run_start_x = self.hit_test_x(range.start);
+        let mut prev_x = run_start_x;
+        let mut offset = range.start;
+        while offset < range.end {
+            let next = self
+                .next_grapheme_offset(offset)
+                .unwrap_or(range.end)
+                .min(range.end);
+            let next_x = self.hit_test_x(next);
+            if next_x < prev_x {
+                rects.push(Rect::new(
+                    run_start_x.min(prev_x),
+                    0.0,
+                    run_start_x.max(prev_x),
+                    FONT_SIZE,
+                ));
+                run_start_x = prev_x;
+            }
+            offset = next;
+            prev_x = next_x;
+        }
+        rects.push(Rect::new(
+            run_start_x.min(prev_x),
+            0.0,
+            run_start_x.max(prev_x),
+            FONT_SIZE,
+        ));
+        rects
     }
-    fn line_range(&mut self, _char_index: usize) -> Range<usize> {
+    fn line_range(&mut self, _char_index: usize, _affinity: Affinity) -> Range<usize> {
         // we don't have multiple lines, so no matter the input, output is the whole document
         0..self.state.borrow().text.len()
     }
 }
 
+impl AppTextInputHandler {
+    /// Applies a document mutation without recording it in the undo history. Used both by
+    /// `replace_range` (after it records the edit) and by `undo`/`redo` (which must apply the
+    /// inverse/reapplied edit without pushing a new revision on top of it).
+    fn apply_edit(&mut self, range: Range<usize>, text: &str) {
+        let mut doc = self.state.borrow_mut();
+        doc.text.replace_range(range, text);
+        doc.refresh_layout();
+        drop(doc);
+        self.window_handle.request_anim_frame();
+    }
+
+    fn hit_test_x(&self, idx: usize) -> f64 {
+        self.state
+            .borrow()
+            .layout
+            .as_ref()
+            .unwrap()
+            .hit_test_text_position(idx)
+            .point
+            .x
+    }
+}
+
 fn main() {
     let app = Application::new().unwrap();
     let mut builder = WindowBuilder::new(app.clone());
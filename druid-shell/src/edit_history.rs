@@ -0,0 +1,220 @@
+//! A linear undo/redo history for text fields built on `TextInputHandler::replace_range`, with
+//! coalescing of consecutive single-character edits into one undo step.
+
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+/// How long a pause between same-kind single-character edits is allowed before the next one
+/// starts a fresh revision instead of coalescing into the current one.
+const COALESCE_WINDOW: Duration = Duration::from_millis(750);
+
+/// Whether an edit is a candidate for coalescing with an adjacent edit into one undo revision.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditKind {
+    /// A single character typed or deleted at the caret, as from ordinary key presses. A run of
+    /// these at a contiguous position within `COALESCE_WINDOW` merges into one revision.
+    Typing,
+    /// A selection replacement, paste, or IME commit. Always starts a fresh revision, so it's
+    /// always undone as the one edit the user asked for, never folded into neighboring typing.
+    Discrete,
+}
+
+/// A single reversible document edit, as captured from a `TextInputHandler::replace_range` call:
+/// the range that was replaced, what was removed, and what was inserted in its place.
+#[derive(Debug, Clone)]
+struct Edit {
+    range: Range<usize>,
+    removed: String,
+    inserted: String,
+}
+
+impl Edit {
+    /// The range this edit occupies in the document after being applied, for chaining coalesced
+    /// edits and for computing the inverse.
+    fn applied_range(&self) -> Range<usize> {
+        self.range.start..self.range.start + self.inserted.len()
+    }
+
+    /// The edit that undoes this one: replacing what it inserted with what it removed.
+    fn inverse(&self) -> Edit {
+        Edit {
+            range: self.applied_range(),
+            removed: self.inserted.clone(),
+            inserted: self.removed.clone(),
+        }
+    }
+}
+
+/// One undo/redo step: a document edit plus the selection to restore on either side of it.
+#[derive(Debug, Clone)]
+struct Revision {
+    edit: Edit,
+    kind: EditKind,
+    selection_before: Range<usize>,
+    selection_after: Range<usize>,
+    recorded_at: Instant,
+}
+
+/// A linear undo/redo history for a single `TextInputHandler`-backed text field.
+///
+/// Each call to `record` wraps one `replace_range` edit into a `Revision` on the undo stack.
+/// Consecutive `EditKind::Typing` edits at a contiguous caret position within `COALESCE_WINDOW`
+/// merge into the current revision, so pressing backspace five times in a row undoes as one step
+/// instead of five; `EditKind::Discrete` edits (selection replacement, paste, IME commit) always
+/// start a fresh one. A branching (tree) history would let `redo` survive a divergent edit made
+/// after an undo, but this is a linear stack: recording a new edit discards the redo tail.
+#[derive(Debug, Default)]
+pub struct EditHistory {
+    undo_stack: Vec<Revision>,
+    redo_stack: Vec<Revision>,
+}
+
+impl EditHistory {
+    pub fn new() -> EditHistory {
+        EditHistory::default()
+    }
+
+    /// Records a `replace_range(range, &inserted)` edit that removed `removed`, with the
+    /// selection as it was immediately before and immediately after the edit.
+    ///
+    /// Coalesces into the previous revision if `kind` is `EditKind::Typing`, the previous
+    /// revision is also `Typing` and still within `COALESCE_WINDOW`, and the two edits are
+    /// contiguous; otherwise starts a new revision. Always clears the redo stack, since recording
+    /// a new edit invalidates whatever had been undone before it.
+    pub fn record(
+        &mut self,
+        kind: EditKind,
+        range: Range<usize>,
+        removed: String,
+        inserted: String,
+        selection_before: Range<usize>,
+        selection_after: Range<usize>,
+    ) {
+        self.redo_stack.clear();
+        let edit = Edit { range, removed, inserted };
+        if kind == EditKind::Typing {
+            if let Some(top) = self.undo_stack.last_mut() {
+                if top.kind == EditKind::Typing
+                    && top.recorded_at.elapsed() < COALESCE_WINDOW
+                    && coalesces(&top.edit, &edit)
+                {
+                    merge(&mut top.edit, edit);
+                    top.selection_after = selection_after;
+                    top.recorded_at = Instant::now();
+                    return;
+                }
+            }
+        }
+        self.undo_stack.push(Revision {
+            edit,
+            kind,
+            selection_before,
+            selection_after,
+            recorded_at: Instant::now(),
+        });
+    }
+
+    /// Pops the most recent revision and returns the edit that undoes it, as
+    /// `(range, text, selection)`: apply `text` via `replace_range(range, text)`, then restore the
+    /// selection with `set_selected_range(selection)`. Returns `None` if there's nothing to undo.
+    pub fn undo(&mut self) -> Option<(Range<usize>, String, Range<usize>)> {
+        let revision = self.undo_stack.pop()?;
+        let inverse = revision.edit.inverse();
+        let selection = revision.selection_before.clone();
+        self.redo_stack.push(revision);
+        Some((inverse.range, inverse.inserted, selection))
+    }
+
+    /// Pops the most recently undone revision and returns the edit that reapplies it, in the same
+    /// `(range, text, selection)` form as `undo`. Returns `None` if there's nothing to redo.
+    pub fn redo(&mut self) -> Option<(Range<usize>, String, Range<usize>)> {
+        let revision = self.redo_stack.pop()?;
+        let range = revision.edit.range.clone();
+        let text = revision.edit.inserted.clone();
+        let selection = revision.selection_after.clone();
+        self.undo_stack.push(revision);
+        Some((range, text, selection))
+    }
+}
+
+/// Whether `next` directly continues `prev` at a contiguous caret position: `next` either
+/// replaces the span right after where `prev` left off (a run of forward typing or forward
+/// deletes), or the span right before it (a run of backspaces).
+fn coalesces(prev: &Edit, next: &Edit) -> bool {
+    next.range.start == prev.applied_range().end || next.range.end == prev.range.start
+}
+
+/// Folds `next` into `prev` so that `prev` describes their combined effect on the document as it
+/// was before `prev` was first applied. See `coalesces` for the two shapes this handles.
+fn merge(prev: &mut Edit, next: Edit) {
+    if next.range.start == prev.applied_range().end {
+        prev.range.end += next.removed.len();
+        prev.removed.push_str(&next.removed);
+        prev.inserted.push_str(&next.inserted);
+    } else {
+        prev.range.start = next.range.start;
+        let mut removed = next.removed;
+        removed.push_str(&prev.removed);
+        prev.removed = removed;
+        let mut inserted = next.inserted;
+        inserted.push_str(&prev.inserted);
+        prev.inserted = inserted;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typing(history: &mut EditHistory, range: Range<usize>, removed: &str, inserted: &str) {
+        let before = range.start..range.start;
+        let after = range.start + inserted.len()..range.start + inserted.len();
+        history.record(EditKind::Typing, range, removed.to_string(), inserted.to_string(), before, after);
+    }
+
+    #[test]
+    fn consecutive_typed_characters_coalesce_into_one_revision() {
+        let mut history = EditHistory::new();
+        typing(&mut history, 0..0, "", "h");
+        typing(&mut history, 1..1, "", "i");
+        assert_eq!(history.undo(), Some((0..2, String::new(), 0..0)));
+        assert_eq!(history.undo(), None);
+    }
+
+    #[test]
+    fn consecutive_backspaces_coalesce_into_one_revision() {
+        let mut history = EditHistory::new();
+        // Backspacing "hi" one character at a time, starting from a caret at the end.
+        history.record(EditKind::Typing, 1..2, "i".to_string(), String::new(), 2..2, 1..1);
+        history.record(EditKind::Typing, 0..1, "h".to_string(), String::new(), 1..1, 0..0);
+        assert_eq!(history.undo(), Some((0..0, "hi".to_string(), 2..2)));
+    }
+
+    #[test]
+    fn typing_separated_by_a_selection_replacement_does_not_coalesce() {
+        let mut history = EditHistory::new();
+        typing(&mut history, 0..0, "", "h");
+        history.record(EditKind::Discrete, 0..1, "h".to_string(), "world".to_string(), 0..1, 0..5);
+        assert_eq!(history.undo(), Some((0..5, "h".to_string(), 0..1)));
+        assert_eq!(history.undo(), Some((0..1, String::new(), 0..0)));
+    }
+
+    #[test]
+    fn undo_then_redo_restores_the_edit_and_selection() {
+        let mut history = EditHistory::new();
+        history.record(EditKind::Discrete, 0..0, String::new(), "hi".to_string(), 0..0, 2..2);
+        let (range, text, selection) = history.undo().unwrap();
+        assert_eq!((range, text.as_str(), selection), (0..2, "", 0..0));
+        let (range, text, selection) = history.redo().unwrap();
+        assert_eq!((range, text.as_str(), selection), (0..0, "hi", 2..2));
+    }
+
+    #[test]
+    fn recording_a_new_edit_after_undo_discards_the_redo_tail() {
+        let mut history = EditHistory::new();
+        typing(&mut history, 0..0, "", "h");
+        history.undo();
+        typing(&mut history, 0..0, "", "x");
+        assert_eq!(history.redo(), None);
+    }
+}
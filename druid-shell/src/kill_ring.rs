@@ -0,0 +1,78 @@
+//! A small named-register store backing Emacs-style mark and kill-ring editing commands
+//! (`setMark:`, `deleteToMark:`, `yank:`, and friends), modeled on a register store like
+//! helix's `Register`.
+
+use std::collections::HashMap;
+
+/// A single named register: a stack of killed/yanked text values.
+///
+/// Only the most recently killed value is read back by `yank:`, but the whole stack is kept so a
+/// future "yank and cycle" command (Emacs `yank-pop`) has something to cycle through.
+#[derive(Debug, Default, Clone)]
+pub struct Register {
+    values: Vec<String>,
+}
+
+impl Register {
+    /// Pushes a newly killed value onto the register.
+    pub fn push(&mut self, value: String) {
+        self.values.push(value);
+    }
+
+    /// Returns the most recently killed value, if any.
+    pub fn last(&self) -> Option<&str> {
+        self.values.last().map(String::as_str)
+    }
+}
+
+/// The register used by `deleteToMark:`/`yank:` when no register is explicitly named.
+const DEFAULT_REGISTER: char = '\0';
+
+/// A collection of named registers, plus the document "mark" position used by Emacs-style
+/// `setMark:`/`selectToMark:`/`deleteToMark:`/`swapWithMark:` editing.
+///
+/// The macOS backend keeps one `KillRing` per window; it outlives any single `TextInputHandler`
+/// borrow so that marks and kills persist across keystrokes.
+#[derive(Debug, Default)]
+pub struct KillRing {
+    registers: HashMap<char, Register>,
+    mark: Option<usize>,
+}
+
+impl KillRing {
+    pub fn new() -> KillRing {
+        KillRing::default()
+    }
+
+    fn register(&mut self, name: char) -> &mut Register {
+        self.registers.entry(name).or_default()
+    }
+
+    /// Pushes killed text onto the default register, for word/line/mark deletion commands.
+    pub fn kill(&mut self, text: String) {
+        if text.is_empty() {
+            return;
+        }
+        self.register(DEFAULT_REGISTER).push(text);
+    }
+
+    /// Returns the most recently killed text, for `yank:`.
+    pub fn yank(&mut self) -> Option<String> {
+        self.register(DEFAULT_REGISTER).last().map(str::to_string)
+    }
+
+    /// Sets the document mark to `offset`, for `setMark:`.
+    pub fn set_mark(&mut self, offset: usize) {
+        self.mark = Some(offset);
+    }
+
+    /// Returns the document mark, if one has been set.
+    pub fn mark(&self) -> Option<usize> {
+        self.mark
+    }
+
+    /// Sets the mark to `offset`, returning the previous mark, for `swapWithMark:`.
+    pub fn swap_mark(&mut self, offset: usize) -> Option<usize> {
+        self.mark.replace(offset)
+    }
+}
@@ -14,21 +14,44 @@
 
 #![allow(non_snake_case)]
 
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::ffi::c_void;
 use std::ops::Range;
 use std::os::raw::c_uchar;
 
 use super::window::get_edit_lock_from_window;
+use crate::kill_ring::KillRing;
 use crate::kurbo::Point;
 use crate::text_input::{
-    Action, Direction, Movement, TextInputHandler, VerticalMovement, WritingDirection,
+    self, Action, Affinity, CompositionStyle, Direction, Movement, TextInputHandler,
+    VerticalMovement, WritingDirection,
 };
 use cocoa::base::{id, nil, BOOL};
-use cocoa::foundation::{NSArray, NSPoint, NSRect, NSSize, NSString, NSUInteger};
-use cocoa::{appkit::NSWindow, foundation::NSNotFound};
+use cocoa::foundation::{NSArray, NSInteger, NSPoint, NSRect, NSSize, NSString, NSUInteger};
+use cocoa::{
+    appkit::{NSApp, NSWindow},
+    foundation::NSNotFound,
+};
 use objc::runtime::{Object, Sel};
 use objc::{class, msg_send, sel, sel_impl};
 
+// AppKit/Foundation attributed-string keys we read off marked text to recover clause boundaries
+// and underline styling. Apple draws a thin underline (NSUnderlineStyleSingle) for already
+// converted clauses and a thick one (NSUnderlineStyleThick) for the clause currently being
+// converted; see https://developer.apple.com/documentation/appkit/nsattributedstring/key.
+extern "C" {
+    #[link_name = "NSMarkedClauseSegment"]
+    static NS_MARKED_CLAUSE_SEGMENT: id;
+    #[link_name = "NSUnderlineStyleAttributeName"]
+    static NS_UNDERLINE_STYLE_ATTRIBUTE_NAME: id;
+    #[link_name = "NSUnderlineColorAttributeName"]
+    static NS_UNDERLINE_COLOR_ATTRIBUTE_NAME: id;
+}
+
+const NS_UNDERLINE_STYLE_SINGLE: NSInteger = 1;
+const NS_UNDERLINE_STYLE_THICK: NSInteger = 2;
+
 // thanks to winit for the custom NSRange code:
 // https://github.com/rust-windowing/winit/pull/518/files#diff-61be96e960785f102cb20ad8464eafeb6edd4245ea40224b3c3206c72cd5bf56R12-R34
 #[repr(C)]
@@ -118,6 +141,7 @@ pub extern "C" fn set_marked_text(
         });
 
     let text_string = parse_attributed_string(&text);
+    let clauses = composition_clauses(&text, text_string);
     // TODO utf8 -> utf16
     edit_lock.replace_range(replace_range.clone(), text_string);
 
@@ -130,6 +154,15 @@ pub extern "C" fn set_marked_text(
         edit_lock.set_composition_range(Some(composition_range));
     };
 
+    if !clauses.is_empty() {
+        let offset = replace_range.start;
+        let clauses: Vec<_> = clauses
+            .into_iter()
+            .map(|(range, style)| (offset + range.start..offset + range.end, style))
+            .collect();
+        edit_lock.set_composition_styling(&clauses);
+    }
+
     // Update the selection
     if let Some(selection_range) =
         decode_nsrange(&mut edit_lock, &selected_range, replace_range.start)
@@ -146,9 +179,83 @@ pub extern "C" fn unmark_text(this: &mut Object, _: Sel) {
     edit_lock.set_composition_range(None);
 }
 
-pub extern "C" fn valid_attributes_for_marked_text(this: &mut Object, _: Sel) -> id {
-    // we don't support any attributes
-    unsafe { NSArray::array(nil) }
+pub extern "C" fn valid_attributes_for_marked_text(_this: &mut Object, _: Sel) -> id {
+    unsafe {
+        NSArray::arrayWithObjects(
+            nil,
+            &[
+                NS_MARKED_CLAUSE_SEGMENT,
+                NS_UNDERLINE_STYLE_ATTRIBUTE_NAME,
+                NS_UNDERLINE_COLOR_ATTRIBUTE_NAME,
+            ],
+        )
+    }
+}
+
+/// Reads the per-clause underline styling off an incoming marked-text `NSAttributedString`,
+/// converting each clause's `NSMarkedClauseSegment` run into a UTF-8 byte range into `text_string`
+/// (the plain-text contents of `text`, as already extracted by `parse_attributed_string`).
+///
+/// Returns an empty `Vec` if `text` is a plain `NSString` with no attributes to read.
+fn composition_clauses(text: &id, text_string: &str) -> Vec<(Range<usize>, CompositionStyle)> {
+    let mut clauses = Vec::new();
+    unsafe {
+        let is_attributed: BOOL = msg_send![*text, isKindOfClass: class!(NSAttributedString)];
+        if !bool::from(is_attributed) {
+            return clauses;
+        }
+        let length: NSUInteger = msg_send![*text, length];
+        let mut idx: NSUInteger = 0;
+        while idx < length {
+            let mut clause_range = NSRange::new(0, 0);
+            let _: id = msg_send![*text,
+                attribute: NS_MARKED_CLAUSE_SEGMENT
+                atIndex: idx
+                effectiveRange: &mut clause_range as *mut NSRange
+            ];
+            if clause_range.length == 0 {
+                // no clause-segment attribute here; treat the rest of the run as one clause
+                clause_range = NSRange::new(idx, length - idx);
+            }
+            let underline: id = msg_send![*text,
+                attribute: NS_UNDERLINE_STYLE_ATTRIBUTE_NAME
+                atIndex: idx
+                effectiveRange: std::ptr::null_mut::<NSRange>()
+            ];
+            let thickness: NSInteger = if underline == nil {
+                0
+            } else {
+                msg_send![underline, integerValue]
+            };
+            let style = match thickness {
+                NS_UNDERLINE_STYLE_THICK => CompositionStyle::Selected,
+                NS_UNDERLINE_STYLE_SINGLE => CompositionStyle::Converted,
+                _ => CompositionStyle::Unconverted,
+            };
+            clauses.push((utf16_range_to_utf8(text_string, &clause_range), style));
+            idx = clause_range.location + clause_range.length;
+        }
+    }
+    clauses
+}
+
+/// Converts a UTF-16 `NSRange` into a UTF-8 byte range within the standalone string `s` (as
+/// opposed to `decode_nsrange`, which converts a document-relative `NSRange`).
+fn utf16_range_to_utf8(s: &str, range: &NSRange) -> Range<usize> {
+    let start = utf16_offset_to_utf8(s, range.location as usize);
+    let end = utf16_offset_to_utf8(s, range.location as usize + range.length as usize);
+    start..end
+}
+
+fn utf16_offset_to_utf8(s: &str, utf16_offset: usize) -> usize {
+    let mut utf16_count = 0;
+    for (byte_idx, c) in s.char_indices() {
+        if utf16_count >= utf16_offset {
+            return byte_idx;
+        }
+        utf16_count += c.len_utf16();
+    }
+    s.len()
 }
 
 pub extern "C" fn attributed_substring_for_proposed_range(
@@ -228,10 +335,12 @@ pub extern "C" fn first_rect_for_character_range(
     };
     let mut range = decode_nsrange(&mut edit_lock, &character_range, 0).unwrap_or(0..0);
     {
-        let line_range = edit_lock.line_range(range.start);
+        let line_range = edit_lock.line_range(range.start, Affinity::Downstream);
         range.end = usize::min(range.end, line_range.end);
     }
-    let rect = match edit_lock.slice_bounding_box(range.clone()) {
+    // `firstRectForCharacterRange:` asks for only the first rect, even if the range crosses
+    // multiple visual runs (for instance, a bidirectional direction change).
+    let rect = match edit_lock.slice_bounding_box(range.clone()).into_iter().next() {
         Some(v) => v,
         None => return NSRect::new(NSPoint::new(0.0, 0.0), NSSize::new(0.0, 0.0)),
     };
@@ -254,8 +363,46 @@ pub extern "C" fn first_rect_for_character_range(
     }
 }
 
-pub extern "C" fn do_command_by_selector(_this: &mut Object, _: Sel, cmd: Sel) {
-    let cmd = match cmd.name() {
+thread_local! {
+    /// Per-window `KillRing`s, keyed by the address of the window's content view. There's no
+    /// window-close hook in this module to remove an entry once its window goes away, so (as with
+    /// the window itself) it simply lives for the rest of the process.
+    static KILL_RINGS: RefCell<HashMap<usize, KillRing>> = RefCell::new(HashMap::new());
+}
+
+/// Returns the `KillRing` belonging to `this`'s window, creating one on first use. Mirrors
+/// `get_edit_lock_from_window`'s per-window lookup, but for the Emacs-style mark/kill-ring state
+/// tracked in `KillRing` rather than the document itself.
+fn get_kill_ring_from_window(this: &mut Object) -> &mut KillRing {
+    let key = this as *mut Object as usize;
+    KILL_RINGS.with(|rings| {
+        let mut rings = rings.borrow_mut();
+        rings.entry(key).or_insert_with(KillRing::new);
+        // SAFETY: `this` is a stable address for the lifetime of its window, and every caller uses
+        // the returned reference only for the duration of a single statement, so this never
+        // aliases another live reference into the same table.
+        unsafe { &mut *(rings.get_mut(&key).unwrap() as *mut KillRing) }
+    })
+}
+
+/// Opens the system character/emoji picker (the macOS Character Viewer) targeting `this`'s window.
+/// Called from `WindowHandle::show_character_palette`, the same way `first_rect_for_character_range`
+/// and friends take the view they act on rather than assuming it's the key window.
+///
+/// This doesn't need an edit lock of its own: AppKit delivers whatever character gets picked back
+/// to the first responder through `insertText:replacementRange:` (`insert_text`, above), so it
+/// lands in the document via the ordinary `replace_range` path, the same as a typed character,
+/// rather than through a synthetic keypress.
+pub fn show_character_palette(this: &mut Object) {
+    unsafe {
+        let window: id = msg_send![this as *mut _, window];
+        let () = msg_send![window, makeKeyWindow];
+        let () = msg_send![NSApp(), orderFrontCharacterPalette: nil];
+    }
+}
+
+pub extern "C" fn do_command_by_selector(this: &mut Object, _: Sel, cmd: Sel) {
+    let action = match cmd.name() {
         // see https://developer.apple.com/documentation/appkit/nsstandardkeybindingresponding?language=objc
         // and https://support.apple.com/en-us/HT201236
         // and https://support.apple.com/lv-lv/guide/mac-help/mh21243/mac
@@ -271,7 +418,7 @@ pub extern "C" fn do_command_by_selector(_this: &mut Object, _: Sel, cmd: Sel) {
         "deleteToBeginningOfParagraph:" => Some(Action::Delete(Movement::ParagraphStart)),
         "deleteToEndOfLine:" => Some(Action::Delete(Movement::Line(Direction::Downstream))),
         "deleteToEndOfParagraph:" => Some(Action::Delete(Movement::ParagraphEnd)),
-        "deleteToMark:" => None, // TODO
+        "deleteToMark:" => Some(Action::DeleteToMark),
         "deleteWordBackward:" => Some(Action::Delete(Movement::Word(Direction::Upstream))),
         "deleteWordForward:" => Some(Action::Delete(Movement::Word(Direction::Downstream))),
         "indent:" => Some(Action::Indent),
@@ -316,9 +463,11 @@ pub extern "C" fn do_command_by_selector(_this: &mut Object, _: Sel, cmd: Sel) {
         "moveBackwardAndModifySelection:" => Some(Action::MoveSelecting(Movement::Grapheme(
             Direction::Upstream,
         ))),
-        "moveDown:" => Some(Action::Move(Movement::Vertical(VerticalMovement::LineDown))),
+        "moveDown:" => Some(Action::Move(Movement::Vertical(
+            VerticalMovement::DisplayLineDown,
+        ))),
         "moveDownAndModifySelection:" => Some(Action::MoveSelecting(Movement::Vertical(
-            VerticalMovement::LineDown,
+            VerticalMovement::DisplayLineDown,
         ))),
         "moveForward:" => Some(Action::Move(Movement::Grapheme(Direction::Downstream))),
         "moveForwardAndModifySelection:" => Some(Action::MoveSelecting(Movement::Grapheme(
@@ -374,9 +523,9 @@ pub extern "C" fn do_command_by_selector(_this: &mut Object, _: Sel, cmd: Sel) {
         "moveToRightEndOfLineAndModifySelection:" => {
             Some(Action::MoveSelecting(Movement::Line(Direction::Right)))
         }
-        "moveUp:" => Some(Action::Move(Movement::Vertical(VerticalMovement::LineUp))),
+        "moveUp:" => Some(Action::Move(Movement::Vertical(VerticalMovement::DisplayLineUp))),
         "moveUpAndModifySelection:" => Some(Action::MoveSelecting(Movement::Vertical(
-            VerticalMovement::LineUp,
+            VerticalMovement::DisplayLineUp,
         ))),
         "moveWordBackward:" => Some(Action::Move(Movement::Word(Direction::Upstream))),
         "moveWordBackwardAndModifySelection:" => {
@@ -403,6 +552,7 @@ pub extern "C" fn do_command_by_selector(_this: &mut Object, _: Sel, cmd: Sel) {
             VerticalMovement::PageUp,
         ))),
         "quickLookPreviewItems:" => None, // TODO
+        "redo:" => Some(Action::Redo),
         "scrollLineDown:" => Some(Action::Scroll(VerticalMovement::LineDown)),
         "scrollLineUp:" => Some(Action::Scroll(VerticalMovement::LineUp)),
         "scrollPageDown:" => Some(Action::Scroll(VerticalMovement::PageDown)),
@@ -412,23 +562,81 @@ pub extern "C" fn do_command_by_selector(_this: &mut Object, _: Sel, cmd: Sel) {
         "selectAll:" => Some(Action::SelectAll),
         "selectLine:" => Some(Action::SelectLine),
         "selectParagraph:" => Some(Action::SelectParagraph),
-        "selectToMark:" => None, // TODO
+        "selectToMark:" => Some(Action::SelectToMark),
         "selectWord:" => Some(Action::SelectWord),
-        "setMark:" => None,      // TODO
-        "swapWithMark:" => None, // TODO
+        "setMark:" => Some(Action::SetMark),
+        "swapWithMark:" => Some(Action::SwapWithMark),
         "transpose:" => Some(Action::Transpose),
         "transposeWords:" => Some(Action::TransposeWord),
+        "undo:" => Some(Action::Undo),
         "uppercaseWord:" => Some(Action::UppercaseWord),
-        "yank:" => None, // TODO
+        "yank:" => Some(Action::Yank),
         e => {
             eprintln!("unknown text editing command from macos: {}", e);
             None
         }
     };
-    println!("{:?}", cmd);
+    let action = match action {
+        Some(v) => v,
+        None => return,
+    };
+    let mut edit_lock = match get_edit_lock_from_window(this, true) {
+        Some(v) => v,
+        None => return,
+    };
+    match action {
+        Action::Delete(movement) => {
+            let range = text_input::deletion_range(&mut *edit_lock, movement);
+            // Plain Backspace/Delete (`Movement::Grapheme`) shouldn't touch the kill ring: only
+            // the word/line/paragraph-oriented deletes are the Emacs-style kill commands the ring
+            // is for, and a stray single character here would bury the last real kill.
+            if !matches!(movement, Movement::Grapheme(_)) {
+                let killed = edit_lock.slice(range).into_owned();
+                get_kill_ring_from_window(this).kill(killed);
+            }
+            edit_lock.handle_action(action);
+        }
+        Action::SetMark => {
+            let caret = edit_lock.selected_range().end;
+            get_kill_ring_from_window(this).set_mark(caret);
+        }
+        Action::SwapWithMark => {
+            let caret = edit_lock.selected_range().end;
+            if let Some(old_mark) = get_kill_ring_from_window(this).swap_mark(caret) {
+                edit_lock.set_selected_range(old_mark..old_mark);
+            }
+        }
+        Action::SelectToMark => {
+            if let Some(mark) = get_kill_ring_from_window(this).mark() {
+                let caret = edit_lock.selected_range().end;
+                let range = if mark < caret { mark..caret } else { caret..mark };
+                edit_lock.set_selected_range(range);
+            }
+        }
+        Action::DeleteToMark => {
+            if let Some(mark) = get_kill_ring_from_window(this).mark() {
+                let caret = edit_lock.selected_range().end;
+                let range = if mark < caret { mark..caret } else { caret..mark };
+                let killed = edit_lock.slice(range.clone()).into_owned();
+                edit_lock.replace_range(range.clone(), "");
+                get_kill_ring_from_window(this).kill(killed);
+                edit_lock.set_selected_range(range.start..range.start);
+            }
+        }
+        Action::Yank => {
+            if let Some(text) = get_kill_ring_from_window(this).yank() {
+                let range = edit_lock.selected_range();
+                edit_lock.replace_range(range.clone(), &text);
+                let caret = range.start + text.len();
+                edit_lock.set_selected_range(caret..caret);
+            }
+        }
+        other => edit_lock.handle_action(other),
+    }
 }
 
-/// Parses the UTF-16 `NSRange` into a UTF-8 `Range<usize>`.
+/// Parses the UTF-16 `NSRange` into a UTF-8 `Range<usize>`, snapped outward to the nearest
+/// enclosing grapheme cluster boundaries and clamped to the document's length.
 /// `start_offset` is the UTF-8 offset into the document that `range` values are relative to. Set it to `0` if `range`
 /// is absolute instead of relative.
 /// Returns `None` if `range` was invalid; macOS often uses this to indicate some special null value.
@@ -440,21 +648,105 @@ fn decode_nsrange(
     if range.location as usize >= i32::max_value() as usize {
         return None;
     }
-    // TODO fix offsets if they don't lie on a unicode boundary, or if they're beyond the end of the document
-    let start_offset_utf16 = edit_lock.utf8_to_utf16(0..start_offset);
+    let start_offset_utf16 = utf8_offsets_to_utf16(edit_lock, &[start_offset])[0];
     let location_utf16 = range.location as usize + start_offset_utf16;
-    let length_utf16 = range.length as usize + start_offset_utf16;
-    let start_utf8 = edit_lock.utf16_to_utf8(0..location_utf16);
-    let end_utf8 =
-        start_utf8 + edit_lock.utf16_to_utf8(location_utf16..location_utf16 + length_utf16);
+    let end_utf16 = location_utf16 + range.length as usize;
+    let utf8 = utf16_offsets_to_utf8(edit_lock, &[location_utf16, end_utf16]);
+    let start_utf8 = floor_to_grapheme_boundary(edit_lock, utf8[0]);
+    let end_utf8 = ceil_to_grapheme_boundary(edit_lock, utf8[1].max(start_utf8));
     Some(start_utf8..end_utf8)
 }
 
 // Encodes the UTF-8 `Range<usize>` into a UTF-16 `NSRange`.
 fn encode_nsrange(edit_lock: &mut Box<dyn TextInputHandler>, range: Range<usize>) -> NSRange {
-    let start = edit_lock.utf8_to_utf16(0..range.start);
-    let len = edit_lock.utf8_to_utf16(range);
-    NSRange::new(start as NSUInteger, len as NSUInteger)
+    let utf16 = utf8_offsets_to_utf16(edit_lock, &[range.start, range.end]);
+    NSRange::new(utf16[0] as NSUInteger, (utf16[1] - utf16[0]) as NSUInteger)
+}
+
+/// Converts several UTF-8 byte offsets into this document's UTF-16 code-unit offsets in a single
+/// pass over the text, rather than calling `edit_lock.utf8_to_utf16` (which rescans from the start
+/// of the document) once per offset.
+fn utf8_offsets_to_utf16(edit_lock: &mut Box<dyn TextInputHandler>, utf8_offsets: &[usize]) -> Vec<usize> {
+    let len = edit_lock.len();
+    let max_offset = utf8_offsets.iter().copied().max().unwrap_or(0).min(len);
+    let text = edit_lock.slice(0..max_offset);
+    let mut results = vec![0usize; utf8_offsets.len()];
+    let mut utf16_count = 0;
+    for (byte_idx, c) in text.char_indices() {
+        for (target, result) in utf8_offsets.iter().zip(results.iter_mut()) {
+            if *target == byte_idx {
+                *result = utf16_count;
+            }
+        }
+        utf16_count += c.len_utf16();
+    }
+    for (target, result) in utf8_offsets.iter().zip(results.iter_mut()) {
+        if *target >= max_offset {
+            *result = utf16_count;
+        }
+    }
+    results
+}
+
+/// Converts several UTF-16 code-unit offsets into this document's UTF-8 byte offsets in a single
+/// pass over the text, rather than calling `edit_lock.utf16_to_utf8` (which rescans from the start
+/// of the document) once per offset. Offsets beyond the end of the document clamp to its length.
+/// An offset that splits a surrogate pair maps to a byte inside that character (not a char
+/// boundary), which `floor_to_grapheme_boundary`/`ceil_to_grapheme_boundary` then round outward.
+fn utf16_offsets_to_utf8(edit_lock: &mut Box<dyn TextInputHandler>, utf16_offsets: &[usize]) -> Vec<usize> {
+    let len = edit_lock.len();
+    let text = edit_lock.slice(0..len);
+    let mut results = vec![len; utf16_offsets.len()];
+    let mut utf16_count = 0;
+    for (byte_idx, c) in text.char_indices() {
+        for (target, result) in utf16_offsets.iter().zip(results.iter_mut()) {
+            if utf16_count == *target {
+                *result = byte_idx;
+            } else if utf16_count < *target && *target < utf16_count + c.len_utf16() {
+                *result = byte_idx + 1;
+            }
+        }
+        utf16_count += c.len_utf16();
+    }
+    for (target, result) in utf16_offsets.iter().zip(results.iter_mut()) {
+        if utf16_count == *target {
+            *result = len;
+        }
+    }
+    results
+}
+
+/// Snaps `byte_offset` down to the start of the grapheme cluster it falls within (or leaves it
+/// alone, if it's already on a cluster boundary), clamping to `[0, len]`.
+fn floor_to_grapheme_boundary(edit_lock: &mut Box<dyn TextInputHandler>, byte_offset: usize) -> usize {
+    let len = edit_lock.len();
+    let offset = byte_offset.min(len);
+    if offset == 0 || offset == len {
+        return offset;
+    }
+    // `previous_grapheme_offset` lands on the start of whichever cluster `offset` is inside (or
+    // the cluster before it, if `offset` is itself already a boundary); in the latter case,
+    // stepping back forward one cluster recovers `offset` exactly.
+    let prev = edit_lock.previous_grapheme_offset(offset).unwrap_or(0);
+    match edit_lock.next_grapheme_offset(prev) {
+        Some(end) if end <= offset => end,
+        _ => prev,
+    }
+}
+
+/// Snaps `byte_offset` up to the end of the grapheme cluster it falls within (or leaves it alone,
+/// if it's already on a cluster boundary), clamping to `[0, len]`.
+fn ceil_to_grapheme_boundary(edit_lock: &mut Box<dyn TextInputHandler>, byte_offset: usize) -> usize {
+    let len = edit_lock.len();
+    let offset = byte_offset.min(len);
+    if offset == 0 || offset == len {
+        return offset;
+    }
+    let next = edit_lock.next_grapheme_offset(offset).unwrap_or(len);
+    match edit_lock.previous_grapheme_offset(next) {
+        Some(start) if start >= offset => start,
+        _ => next,
+    }
 }
 
 fn parse_attributed_string(text: &id) -> &str {
@@ -470,3 +762,99 @@ fn parse_attributed_string(text: &id) -> &str {
         std::str::from_utf8_unchecked(slice)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kurbo::Rect;
+    use crate::piet::HitTestPoint;
+    use std::borrow::Cow;
+
+    /// A minimal `TextInputHandler` backed by a plain `String`, used to test the UTF-16/UTF-8
+    /// conversion and grapheme-snapping helpers without a real `NSAttributedString`.
+    struct TestDocument(String);
+
+    impl TextInputHandler for TestDocument {
+        fn selected_range(&mut self) -> Range<usize> {
+            0..0
+        }
+        fn set_selected_range(&mut self, _range: Range<usize>) {}
+        fn composition_range(&mut self) -> Option<Range<usize>> {
+            None
+        }
+        fn set_composition_range(&mut self, _range: Option<Range<usize>>) {}
+        fn is_char_boundary(&mut self, i: usize) -> bool {
+            self.0.is_char_boundary(i)
+        }
+        fn len(&mut self) -> usize {
+            self.0.len()
+        }
+        fn slice<'a>(&'a mut self, range: Range<usize>) -> Cow<'a, str> {
+            self.0[range].into()
+        }
+        fn replace_range(&mut self, range: Range<usize>, text: &str) {
+            self.0.replace_range(range, text);
+        }
+        fn hit_test_point(&mut self, _point: Point) -> HitTestPoint {
+            HitTestPoint::default()
+        }
+        fn line_range(&mut self, _char_index: usize, _affinity: Affinity) -> Range<usize> {
+            0..self.0.len()
+        }
+        fn bounding_box(&mut self) -> Option<Rect> {
+            None
+        }
+        fn slice_bounding_box(&mut self, _range: Range<usize>) -> Vec<Rect> {
+            Vec::new()
+        }
+    }
+
+    fn boxed(text: &str) -> Box<dyn TextInputHandler> {
+        Box::new(TestDocument(text.to_string()))
+    }
+
+    #[test]
+    fn decode_nsrange_round_trips_ascii() {
+        let mut doc = boxed("hello world");
+        let range = encode_nsrange(&mut doc, 2..7);
+        assert_eq!(decode_nsrange(&mut doc, &range, 0), Some(2..7));
+    }
+
+    #[test]
+    fn decode_nsrange_snaps_astral_emoji_to_cluster_boundary() {
+        // "a" + family emoji (a single grapheme cluster spanning two UTF-16 surrogate pairs
+        // joined with ZWJ) + "b"
+        let mut doc = boxed("a\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}b");
+        // the family emoji is 2 UTF-16 code units per person plus a ZWJ each; ask for a range
+        // that lands in the middle of it and confirm it's widened to cover the whole cluster.
+        let emoji_start_utf16 = "a".encode_utf16().count();
+        let mid_cluster = NSRange::new(emoji_start_utf16 as NSUInteger, 2);
+        let decoded = decode_nsrange(&mut doc, &mid_cluster, 0).unwrap();
+        let emoji_start_utf8 = "a".len();
+        let emoji_end_utf8 = doc.len() - "b".len();
+        assert_eq!(decoded, emoji_start_utf8..emoji_end_utf8);
+    }
+
+    #[test]
+    fn decode_nsrange_snaps_combining_mark_to_base_character() {
+        // "e" followed by a combining acute accent is one grapheme cluster.
+        let mut doc = boxed("e\u{0301}f");
+        let mid_cluster = NSRange::new(1, 0);
+        let decoded = decode_nsrange(&mut doc, &mid_cluster, 0).unwrap();
+        assert_eq!(decoded, 0.."e\u{0301}".len());
+    }
+
+    #[test]
+    fn decode_nsrange_out_of_range_location_is_none() {
+        let mut doc = boxed("hi");
+        let range = NSRange::new(NSNotFound as NSUInteger, 0);
+        assert_eq!(decode_nsrange(&mut doc, &range, 0), None);
+    }
+
+    #[test]
+    fn decode_nsrange_clamps_past_end_of_document() {
+        let mut doc = boxed("hi");
+        let range = NSRange::new(0, 100);
+        assert_eq!(decode_nsrange(&mut doc, &range, 0), Some(0..2));
+    }
+}
@@ -5,6 +5,7 @@ use crate::window::WinHandler;
 use crate::keyboard::{KbKey, KeyEvent};
 use crate::kurbo::{Rect, Point};
 use crate::piet::HitTestPoint;
+use unic_segment::{Graphemes, WordBounds};
 
 /// A token that uniquely identifies a text input field inside a window.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Hash)]
@@ -32,6 +33,155 @@ impl TextInputToken {
     }
 }
 
+/// A direction of motion or deletion, relative to either the document or the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Towards the start of the document.
+    Upstream,
+    /// Towards the end of the document.
+    Downstream,
+    /// Towards the left edge of the screen, independent of writing direction.
+    Left,
+    /// Towards the right edge of the screen, independent of writing direction.
+    Right,
+}
+
+/// A unit of text motion, used by both caret movement and deletion commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Movement {
+    /// Move by one grapheme cluster.
+    Grapheme(Direction),
+    /// Move by one word.
+    Word(Direction),
+    /// Move to the edge of the current (soft- or hard-wrapped) line.
+    Line(Direction),
+    /// Move vertically, by line, page, or to the start/end of the document.
+    Vertical(VerticalMovement),
+    /// Move to the start of the previous paragraph.
+    ParagraphPrev,
+    /// Move to the start of the next paragraph.
+    ParagraphNext,
+    /// Move to the start of the current paragraph.
+    ParagraphStart,
+    /// Move to the end of the current paragraph.
+    ParagraphEnd,
+}
+
+/// A unit of vertical text motion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerticalMovement {
+    /// Move to the same column in the previous/next logical (newline-delimited) line.
+    LineUp,
+    LineDown,
+    /// Move to the same horizontal pixel position in the previous/next displayed (soft-wrapped)
+    /// row, the way arrow-key vertical motion works. Repeated motion through short lines sticks
+    /// to a "goal column" (see `TextInputHandler::vertical_movement_goal`) instead of drifting.
+    DisplayLineUp,
+    DisplayLineDown,
+    PageUp,
+    PageDown,
+    DocumentStart,
+    DocumentEnd,
+}
+
+/// The base writing direction of a paragraph or selection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WritingDirection {
+    LeftToRight,
+    RightToLeft,
+    /// Infer the writing direction from the text itself.
+    Natural,
+}
+
+/// Which side of a directional boundary a caret or line lookup resolves to, when the logical
+/// offset alone is ambiguous. The clearest example is the boundary between a left-to-right and a
+/// right-to-left run: the same offset sits at the visual end of one run and the visual start of
+/// the other, and `Affinity` says which one a query like `TextInputHandler::line_range` means.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Affinity {
+    /// Resolve to the line or run ending at the offset.
+    Upstream,
+    /// Resolve to the line or run starting at the offset.
+    Downstream,
+}
+
+/// The visual style of a single clause within an input method's composition (marked/preedit)
+/// text, as reported by `NSMarkedClauseSegment` and the underline attributes on the incoming
+/// `NSAttributedString`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompositionStyle {
+    /// A clause the input method has already converted, conventionally drawn with a thin
+    /// underline.
+    Converted,
+    /// The clause currently being worked on/converted, conventionally drawn with a thick
+    /// underline.
+    Selected,
+    /// A clause the input method has not converted yet, conventionally drawn with a dotted
+    /// underline.
+    Unconverted,
+}
+
+/// A high-level text editing command.
+///
+/// This is the vocabulary that platform key bindings (for instance, macOS's
+/// `doCommandBySelector:`) get decoded into; [`TextInputHandler::handle_action`] turns it back
+/// into concrete edits via the rest of the trait.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Move the caret, collapsing any selection.
+    Move(Movement),
+    /// Move the selection's active end, extending or shrinking the selection.
+    MoveSelecting(Movement),
+    /// Delete the current selection, or the text covered by `Movement` if the selection is a caret.
+    Delete(Movement),
+    /// Scroll the viewport without moving the caret.
+    Scroll(VerticalMovement),
+    /// Scroll the viewport so that the selection is visible.
+    ScrollToSelection,
+    SelectAll,
+    SelectLine,
+    SelectParagraph,
+    SelectWord,
+    /// Delete the selection, or the grapheme cluster before the caret if the selection is a
+    /// caret. A precomposed character is deleted whole; it is not decomposed first.
+    DecomposingBackspace,
+    InsertBacktab,
+    InsertTab {
+        ignore_autocomplete: bool,
+    },
+    InsertLineBreak,
+    InsertNewLine {
+        ignore_autocomplete: bool,
+    },
+    InsertParagraphBreak,
+    Indent,
+    CapitalizeWord,
+    LowercaseWord,
+    UppercaseWord,
+    /// Toggle the case of the character before the caret.
+    SwapLetterCase,
+    /// Swap the two characters before the caret.
+    Transpose,
+    /// Swap the word before the caret with the word after it.
+    TransposeWord,
+    SetParagraphWritingDirection(WritingDirection),
+    SetSelectionWritingDirection(WritingDirection),
+    /// Set the Emacs-style mark to the current caret position.
+    SetMark,
+    /// Extend the selection to the mark.
+    SelectToMark,
+    /// Delete the text between the caret and the mark, killing it onto the kill ring.
+    DeleteToMark,
+    /// Swap the mark with the current caret position.
+    SwapWithMark,
+    /// Insert the most recently killed text, from the kill ring.
+    Yank,
+    /// Undo the most recent edit.
+    Undo,
+    /// Redo the most recently undone edit.
+    Redo,
+}
+
 /// All ranges, lengths, and indices are specified in UTF-8 code units, unless specified otherwise.
 pub trait TextInputHandler {
     /// Gets the range of the document that is currently selected.
@@ -56,6 +206,16 @@ pub trait TextInputHandler {
     /// from `TextInputHandler::len()`.
     fn set_composition_range(&mut self, range: Option<Range<usize>>);
 
+    /// Tells the document how to draw each clause of the current composition region, as reported
+    /// by the input method. `ranges` covers (at most) the current `composition_range`; any part of
+    /// the composition region not covered by `ranges` has no particular style.
+    ///
+    /// The default implementation does nothing, since not every consumer renders per-clause
+    /// underlines.
+    fn set_composition_styling(&mut self, ranges: &[(Range<usize>, CompositionStyle)]) {
+        let _ = ranges;
+    }
+
     /// Returns true if `i==0`, `i==TextInputHandler::len()`, or `i` is the first byte of a UTF-8 code point sequence.
     /// Returns false otherwise, including if `i>TextInputHandler::len()`.
     /// Equivalent in functionality to `String::is_char_boundary`.
@@ -99,19 +259,715 @@ pub trait TextInputHandler {
     fn hit_test_point(&mut self, point: Point) -> HitTestPoint;
 
     /// Returns the character range of the line (soft- or hard-wrapped) containing the character
-    /// specified by `char_index`.
-    /// TODO affinity?
-    fn line_range(&mut self, char_index: usize) -> Range<usize>;
+    /// specified by `char_index`. If `char_index` sits exactly on a line boundary, `affinity`
+    /// chooses which of the two adjoining lines it resolves to.
+    fn line_range(&mut self, char_index: usize, affinity: Affinity) -> Range<usize>;
 
     /// Returns the bounding box, in window coordinates, of the visible text document. For instance,
     /// a text box's bounding box would be the rectangle of the border surrounding it, even if the text box is empty.
     /// If the text document is completely offscreen, return `None`.
     fn bounding_box(&mut self) -> Option<Rect>;
 
-    /// Returns the bounding box, in window coordinates, of the range of text specified by `range`.
+    /// Returns the bounding boxes, in window coordinates, of the range of text specified by
+    /// `range`: one rect per visually contiguous run the range crosses. A logically contiguous
+    /// range maps to more than one rect where it crosses a directional boundary in bidirectional
+    /// text (for instance, a selection spanning out of a right-to-left word into the
+    /// left-to-right text around it); plain unidirectional text always yields exactly one.
     /// Ranges will always be equal to or a subrange of some line range returned by `TextInputHandler::line_range`.
     /// If a range spans multiple lines, `slice_bounding_box` may panic.
-    fn slice_bounding_box(&mut self, range: Range<usize>) -> Option<Rect>;
+    /// Returns an empty `Vec` if the range is not currently visible.
+    fn slice_bounding_box(&mut self, range: Range<usize>) -> Vec<Rect>;
+
+    /// Returns the sticky horizontal pixel position ("goal column") that repeated
+    /// `VerticalMovement::DisplayLineUp`/`DisplayLineDown`/`PageUp`/`PageDown` motion should
+    /// preserve, or `None` if no such motion run is currently in progress.
+    ///
+    /// The default implementation doesn't persist anything, so every display-line motion
+    /// re-derives its goal from the caret's current position; override this (backed by a field on
+    /// your document) to get the usual arrow-key behavior of sticking to a column through short
+    /// lines instead of drifting towards them.
+    fn vertical_movement_goal(&mut self) -> Option<f64> {
+        None
+    }
+
+    /// Records the sticky horizontal pixel position for the current display-line motion run.
+    /// `handle_action` calls this with `None` for `Action::Delete` and for any `Action::Move`/
+    /// `Action::MoveSelecting` that isn't display-line or page motion; other actions, and edits
+    /// made directly through `replace_range`, leave the stored goal as it is.
+    fn set_vertical_movement_goal(&mut self, goal: Option<f64>) {
+        let _ = goal;
+    }
+
+    /// Returns the fixed end of the selection that an in-progress run of `Action::MoveSelecting`
+    /// calls is extending away from, or `None` if there isn't one in progress.
+    ///
+    /// The default implementation doesn't persist anything, so every `Action::MoveSelecting` call
+    /// re-derives the anchor from the selection's current start; override this (backed by a field
+    /// on your document) so that once a backward extension pushes the active edge past the
+    /// anchor, further extension keeps pivoting off the same anchor instead of the edge that just
+    /// moved.
+    fn selection_anchor(&mut self) -> Option<usize> {
+        None
+    }
+
+    /// Records the fixed end of the current `Action::MoveSelecting` run. `handle_action` never
+    /// clears it; instead, a stored anchor that is no longer either end of the current selection
+    /// is ignored and replaced by the selection's start.
+    fn set_selection_anchor(&mut self, anchor: Option<usize>) {
+        let _ = anchor;
+    }
+
+    /// Undoes the most recently recorded edit, returning `true` if there was anything to undo.
+    ///
+    /// The default implementation doesn't keep an edit history, so it always returns `false`;
+    /// override this (backed by an edit history, see `crate::edit_history`) to support
+    /// `Action::Undo`.
+    fn undo(&mut self) -> bool {
+        false
+    }
+
+    /// Reapplies the most recently undone edit, returning `true` if there was anything to redo.
+    ///
+    /// The default implementation doesn't keep an edit history, so it always returns `false`;
+    /// override this (backed by an edit history, see `crate::edit_history`) to support
+    /// `Action::Redo`.
+    fn redo(&mut self) -> bool {
+        false
+    }
+
+    /// Returns the start of the next grapheme cluster after `i`, or `None` if `i` is already at or
+    /// past the end of the document.
+    ///
+    /// The default implementation scans a bounded window of text around `i` (via `slice`) with a
+    /// grapheme-cluster segmenter, widening the window and rescanning if the boundary it finds
+    /// turns out to just be where the window happened to end rather than a real cluster boundary,
+    /// so that arbitrarily long combining sequences still move as one unit. Override this if you
+    /// have a faster way to find grapheme boundaries, for instance one backed by a text layout.
+    fn next_grapheme_offset(&mut self, i: usize) -> Option<usize> {
+        if i >= self.len() {
+            return None;
+        }
+        Some(next_grapheme_boundary(self, i))
+    }
+
+    /// Returns the start of the grapheme cluster before `i`, or `None` if `i` is already at the
+    /// start of the document. See `next_grapheme_offset` for how the default implementation works.
+    fn previous_grapheme_offset(&mut self, i: usize) -> Option<usize> {
+        if i == 0 {
+            return None;
+        }
+        Some(prev_grapheme_boundary(self, i))
+    }
+
+    /// Returns the end of the word after `i` (or, if `i` is within a run of whitespace/
+    /// punctuation, the end of the next word past it), or `None` if `i` is already at or past the
+    /// end of the document. See `offset_for_movement`'s handling of `Movement::Word`.
+    fn next_word_offset(&mut self, i: usize) -> Option<usize> {
+        if i >= self.len() {
+            return None;
+        }
+        Some(next_word_boundary(self, i))
+    }
+
+    /// Returns the start of the word before `i`, or `None` if `i` is already at the start of the
+    /// document.
+    fn previous_word_offset(&mut self, i: usize) -> Option<usize> {
+        if i == 0 {
+            return None;
+        }
+        Some(prev_word_boundary(self, i))
+    }
+
+    /// Applies a high-level editing [`Action`] to the document.
+    ///
+    /// This is the single point where the movement/selection/deletion vocabulary produced by
+    /// decoding a platform key binding (see the macOS `doCommandBySelector:` handling) turns into
+    /// concrete calls to `replace_range` and `set_selected_range`. The default implementation only
+    /// has the rest of this trait to work with: grapheme motion goes through
+    /// `next_grapheme_offset`/`previous_grapheme_offset`, and word motion through UAX #29 word
+    /// segmentation of the text returned by `slice`; implementers that need platform-accurate
+    /// motion can override it.
+    fn handle_action(&mut self, action: Action) {
+        match action {
+            Action::Move(movement) => {
+                if !uses_sticky_column(movement) {
+                    self.set_vertical_movement_goal(None);
+                }
+                let caret = self.selected_range().end;
+                let target = offset_for_movement(self, movement, caret);
+                self.set_selected_range(target..target);
+            }
+            Action::MoveSelecting(movement) => {
+                if !uses_sticky_column(movement) {
+                    self.set_vertical_movement_goal(None);
+                }
+                let range = self.selected_range();
+                // The anchor only carries over from the last call if it's still one of the
+                // current selection's ends; otherwise something else set the selection since
+                // then, so start a fresh anchor at its current start, same as a plain click would.
+                let anchor = match self.selection_anchor() {
+                    Some(anchor) if anchor == range.start || anchor == range.end => anchor,
+                    _ => range.start,
+                };
+                let active = if anchor == range.start { range.end } else { range.start };
+                let target = offset_for_movement(self, movement, active);
+                let new_range = if target <= anchor {
+                    target..anchor
+                } else {
+                    anchor..target
+                };
+                self.set_selection_anchor(Some(anchor));
+                self.set_selected_range(new_range);
+            }
+            Action::Delete(movement) => {
+                self.set_vertical_movement_goal(None);
+                let delete_range = deletion_range(self, movement);
+                self.replace_range(delete_range.clone(), "");
+                self.set_selected_range(delete_range.start..delete_range.start);
+            }
+            Action::DecomposingBackspace => {
+                let range = self.selected_range();
+                let delete_range = if range.start != range.end {
+                    range
+                } else {
+                    prev_char_boundary(self, range.start)..range.start
+                };
+                self.replace_range(delete_range.clone(), "");
+                self.set_selected_range(delete_range.start..delete_range.start);
+            }
+            Action::SelectAll => {
+                let len = self.len();
+                self.set_selected_range(0..len);
+            }
+            Action::SelectLine => {
+                let caret = self.selected_range().end;
+                let range = self.line_range(caret, Affinity::Downstream);
+                self.set_selected_range(range);
+            }
+            Action::SelectParagraph => {
+                let caret = self.selected_range().end;
+                let range = paragraph_range(self, caret);
+                self.set_selected_range(range);
+            }
+            Action::SelectWord => {
+                let caret = self.selected_range().end;
+                let range = word_range(self, caret);
+                self.set_selected_range(range);
+            }
+            Action::InsertTab { .. } => insert_at_caret(self, "\t"),
+            Action::InsertLineBreak | Action::InsertNewLine { .. } | Action::InsertParagraphBreak => {
+                insert_at_caret(self, "\n")
+            }
+            Action::Indent => indent_current_line(self, true),
+            Action::InsertBacktab => indent_current_line(self, false),
+            Action::CapitalizeWord => transform_word_at_caret(self, capitalize),
+            Action::LowercaseWord => transform_word_at_caret(self, |s| s.to_lowercase()),
+            Action::UppercaseWord => transform_word_at_caret(self, |s| s.to_uppercase()),
+            Action::SwapLetterCase => swap_letter_case(self),
+            Action::Transpose => transpose_chars(self),
+            Action::TransposeWord => transpose_words(self),
+            // Scrolling, paragraph writing direction, and autocomplete hints aren't expressible
+            // in terms of a document edit, so there's nothing for the default implementation to
+            // do; a consumer that has a viewport or a styled document can override `handle_action`
+            // and handle these itself.
+            // Mark and kill-ring commands need a `KillRing` to read from and write to, which
+            // isn't something this trait has access to; a consumer that keeps one (see
+            // `crate::kill_ring`) handles these itself before falling back to `handle_action`
+            // for everything else.
+            Action::Scroll(_)
+            | Action::ScrollToSelection
+            | Action::SetParagraphWritingDirection(_)
+            | Action::SetSelectionWritingDirection(_)
+            | Action::SetMark
+            | Action::SelectToMark
+            | Action::DeleteToMark
+            | Action::SwapWithMark
+            | Action::Yank => {}
+            Action::Undo => {
+                self.undo();
+            }
+            Action::Redo => {
+                self.redo();
+            }
+        }
+    }
+}
+
+/// Computes the range that `Action::Delete(movement)` (or an Emacs-style kill command) would
+/// remove: the current selection if it's non-empty, otherwise the span between the caret and
+/// `movement`'s target.
+pub(crate) fn deletion_range(handler: &mut dyn TextInputHandler, movement: Movement) -> Range<usize> {
+    let range = handler.selected_range();
+    if range.start != range.end {
+        return range;
+    }
+    let target = offset_for_movement(handler, movement, range.start);
+    if target < range.start {
+        target..range.start
+    } else {
+        range.start..target
+    }
+}
+
+/// Moves one grapheme cluster downstream from `from`, for `Movement::Grapheme` and anything else
+/// that wants "one visible character," as opposed to one UTF-8 code point.
+fn next_char_boundary(handler: &mut dyn TextInputHandler, from: usize) -> usize {
+    handler.next_grapheme_offset(from).unwrap_or_else(|| handler.len())
+}
+
+/// Moves one grapheme cluster upstream from `from`. See `next_char_boundary`.
+fn prev_char_boundary(handler: &mut dyn TextInputHandler, from: usize) -> usize {
+    handler.previous_grapheme_offset(from).unwrap_or(0)
+}
+
+/// The number of bytes scanned on either side of an offset when hunting for a grapheme cluster
+/// boundary; doubled and retried if a cluster turns out to be longer than this.
+const GRAPHEME_SCAN_WINDOW: usize = 128;
+
+/// Finds the grapheme cluster boundaries within `window` bytes of `offset`, along with whether the
+/// scanned region reached the start/end of the document (as opposed to being cut off mid-cluster).
+fn nearby_grapheme_boundaries(
+    handler: &mut dyn TextInputHandler,
+    offset: usize,
+    window: usize,
+) -> (Vec<usize>, bool, bool) {
+    let len = handler.len();
+    let mut window_start = offset.saturating_sub(window);
+    while window_start > 0 && !handler.is_char_boundary(window_start) {
+        window_start -= 1;
+    }
+    let mut window_end = (offset + window).min(len);
+    while window_end < len && !handler.is_char_boundary(window_end) {
+        window_end += 1;
+    }
+    let text = handler.slice(window_start..window_end);
+    let mut boundaries = vec![window_start];
+    let mut pos = window_start;
+    for grapheme in Graphemes::new(&text) {
+        pos += grapheme.len();
+        boundaries.push(pos);
+    }
+    (boundaries, window_start == 0, window_end == len)
+}
+
+/// Scans forward from `i` (which must be less than `handler.len()`) to the start of the next
+/// grapheme cluster, widening the scan window until the boundary found isn't just an artifact of
+/// where the window was cut off.
+fn next_grapheme_boundary(handler: &mut dyn TextInputHandler, i: usize) -> usize {
+    let mut window = GRAPHEME_SCAN_WINDOW;
+    loop {
+        let (boundaries, _, reached_end) = nearby_grapheme_boundaries(handler, i, window);
+        let window_end = *boundaries.last().unwrap();
+        if let Some(&boundary) = boundaries.iter().find(|&&b| b > i) {
+            if boundary < window_end || reached_end {
+                return boundary;
+            }
+        }
+        window *= 2;
+    }
+}
+
+/// Scans backward from `i` (which must be greater than `0`) to the start of the grapheme cluster
+/// before it. See `next_grapheme_boundary`.
+fn prev_grapheme_boundary(handler: &mut dyn TextInputHandler, i: usize) -> usize {
+    let mut window = GRAPHEME_SCAN_WINDOW;
+    loop {
+        let (boundaries, reached_start, _) = nearby_grapheme_boundaries(handler, i, window);
+        let window_start = boundaries[0];
+        if let Some(&boundary) = boundaries.iter().rev().find(|&&b| b < i) {
+            if boundary > window_start || reached_start {
+                return boundary;
+            }
+        }
+        window *= 2;
+    }
+}
+
+/// Returns whether a `WordBounds` segment counts as a word (as opposed to the whitespace/
+/// punctuation that separates words), per UAX #29: a run starting with an alphanumeric or
+/// underscore character.
+fn is_word_segment(segment: &str) -> bool {
+    segment
+        .chars()
+        .next()
+        .map(|c| c.is_alphanumeric() || c == '_')
+        .unwrap_or(false)
+}
+
+/// Scans forward from `from` to the next UAX #29 word boundary: the end of the word `from` is
+/// inside, or (if `from` is between words) the end of the next word, the way AppKit's
+/// `moveWordForward:` skips over trailing punctuation and spaces to land past the next word.
+fn next_word_boundary(handler: &mut dyn TextInputHandler, from: usize) -> usize {
+    let len = handler.len();
+    let text = handler.slice(from..len);
+    let mut offset = from;
+    for segment in WordBounds::new(&text) {
+        offset += segment.len();
+        if is_word_segment(segment) {
+            break;
+        }
+    }
+    offset
+}
+
+/// Scans backward from `from` to the previous UAX #29 word boundary: the start of the word `from`
+/// is inside, or (if `from` is between words) the start of the previous word, the way AppKit's
+/// `moveWordBackward:` skips back over leading punctuation and spaces to land before the previous
+/// word.
+fn prev_word_boundary(handler: &mut dyn TextInputHandler, from: usize) -> usize {
+    let text = handler.slice(0..from);
+    let segments: Vec<&str> = WordBounds::new(&text).collect();
+    let mut offset = from;
+    for segment in segments.iter().rev() {
+        offset -= segment.len();
+        if is_word_segment(segment) {
+            break;
+        }
+    }
+    offset
+}
+
+/// Expands `caret` to the bounds of the word (or, over punctuation/whitespace, the single UAX #29
+/// separator segment) it falls within, for `selectWord:`/double-click selection. A separator
+/// segment need not cover the whole run of punctuation around the caret.
+fn word_range(handler: &mut dyn TextInputHandler, caret: usize) -> Range<usize> {
+    let len = handler.len();
+    let text = handler.slice(0..len);
+    let mut pos = 0;
+    let mut last_range: Option<Range<usize>> = None;
+    for segment in WordBounds::new(&text) {
+        let end = pos + segment.len();
+        let range = pos..end;
+        if range.contains(&caret) {
+            // A caret sitting exactly on the boundary between two segments reads as "at the end
+            // of the word that just ended" rather than "at the start of whatever comes next" —
+            // but only when the previous segment actually is a word; if it's a separator, the
+            // caret is the start of the next word, e.g. the leading edge of "world" in
+            // "hello world", and the current (word) segment is the right one to select.
+            if caret == range.start {
+                if let Some(prev) = &last_range {
+                    if is_word_segment(&text[prev.clone()]) {
+                        return prev.clone();
+                    }
+                }
+            }
+            return range;
+        }
+        last_range = Some(range);
+        pos = end;
+    }
+    match last_range {
+        Some(range) if caret == len => range,
+        _ => caret..caret,
+    }
+}
+
+/// Finds the bounds of the logical (newline-delimited) paragraph containing `caret`, as opposed
+/// to `TextInputHandler::line_range`'s soft-wrapped line.
+fn paragraph_range(handler: &mut dyn TextInputHandler, caret: usize) -> Range<usize> {
+    let len = handler.len();
+    let before = handler.slice(0..caret);
+    let start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let after = handler.slice(caret..len);
+    let end = after.find('\n').map(|i| caret + i).unwrap_or(len);
+    start..end
+}
+
+const PAGE_LINES: isize = 20;
+
+fn offset_for_movement(handler: &mut dyn TextInputHandler, movement: Movement, from: usize) -> usize {
+    match movement {
+        Movement::Grapheme(Direction::Upstream) | Movement::Grapheme(Direction::Left) => {
+            prev_char_boundary(handler, from)
+        }
+        Movement::Grapheme(Direction::Downstream) | Movement::Grapheme(Direction::Right) => {
+            next_char_boundary(handler, from)
+        }
+        Movement::Word(Direction::Upstream) | Movement::Word(Direction::Left) => {
+            prev_word_boundary(handler, from)
+        }
+        Movement::Word(Direction::Downstream) | Movement::Word(Direction::Right) => {
+            next_word_boundary(handler, from)
+        }
+        Movement::Line(Direction::Upstream) | Movement::Line(Direction::Left) => {
+            handler.line_range(from, Affinity::Upstream).start
+        }
+        Movement::Line(Direction::Downstream) | Movement::Line(Direction::Right) => {
+            handler.line_range(from, Affinity::Downstream).end
+        }
+        Movement::Vertical(vertical) => vertical_target(handler, vertical, from),
+        Movement::ParagraphPrev => paragraph_range(handler, prev_char_boundary(handler, paragraph_range(handler, from).start)).start,
+        Movement::ParagraphNext => paragraph_range(handler, next_char_boundary(handler, paragraph_range(handler, from).end)).end,
+        Movement::ParagraphStart => paragraph_range(handler, from).start,
+        Movement::ParagraphEnd => paragraph_range(handler, from).end,
+    }
+}
+
+/// Returns whether `movement` is one that should preserve (and is driven by)
+/// `TextInputHandler::vertical_movement_goal` across repeated presses, as opposed to movement that
+/// starts a fresh goal the next time display-line motion happens.
+fn uses_sticky_column(movement: Movement) -> bool {
+    matches!(
+        movement,
+        Movement::Vertical(VerticalMovement::DisplayLineUp)
+            | Movement::Vertical(VerticalMovement::DisplayLineDown)
+            | Movement::Vertical(VerticalMovement::PageUp)
+            | Movement::Vertical(VerticalMovement::PageDown)
+    )
+}
+
+fn vertical_target(handler: &mut dyn TextInputHandler, movement: VerticalMovement, caret: usize) -> usize {
+    match movement {
+        VerticalMovement::DocumentStart => 0,
+        VerticalMovement::DocumentEnd => handler.len(),
+        VerticalMovement::LineUp => logical_line_offset(handler, caret, -1),
+        VerticalMovement::LineDown => logical_line_offset(handler, caret, 1),
+        VerticalMovement::DisplayLineUp => display_line_offset(handler, caret, -1),
+        VerticalMovement::DisplayLineDown => display_line_offset(handler, caret, 1),
+        VerticalMovement::PageUp => display_line_offset(handler, caret, -PAGE_LINES),
+        VerticalMovement::PageDown => display_line_offset(handler, caret, PAGE_LINES),
+    }
+}
+
+/// Steps `lines` logical (newline-delimited) paragraphs up (negative) or down (positive) from
+/// `caret`, preserving the caret's byte column within the line on a best-effort basis.
+fn logical_line_offset(handler: &mut dyn TextInputHandler, caret: usize, lines: isize) -> usize {
+    let mut range = paragraph_range(handler, caret);
+    let column = caret - range.start;
+    let len = handler.len();
+    let step = lines.signum();
+    for _ in 0..lines.abs() {
+        let probe = if step < 0 {
+            if range.start == 0 {
+                break;
+            }
+            range.start - 1
+        } else {
+            if range.end >= len {
+                break;
+            }
+            range.end + 1
+        };
+        range = paragraph_range(handler, probe.min(len));
+    }
+    (range.start + column).min(range.end)
+}
+
+/// Steps `lines` soft-wrapped display rows up (negative) or down (positive) from `caret`,
+/// preserving the caret's horizontal pixel position across the run (see
+/// `TextInputHandler::vertical_movement_goal`) the way arrow-key and page-up/down motion works in
+/// most text editors, rather than drifting towards whatever a shorter line's byte column lands on.
+fn display_line_offset(handler: &mut dyn TextInputHandler, caret: usize, lines: isize) -> usize {
+    let goal = match handler.vertical_movement_goal() {
+        Some(goal) => goal,
+        None => {
+            let goal = handler
+                .slice_bounding_box(caret..caret)
+                .first()
+                .map(|r| r.x0)
+                .unwrap_or(0.0);
+            handler.set_vertical_movement_goal(Some(goal));
+            goal
+        }
+    };
+
+    let mut range = handler.line_range(caret, Affinity::Downstream);
+    let len = handler.len();
+    let step = lines.signum();
+    for _ in 0..lines.abs() {
+        let probe = if step < 0 {
+            if range.start == 0 {
+                break;
+            }
+            range.start - 1
+        } else {
+            if range.end >= len {
+                break;
+            }
+            range.end
+        };
+        let affinity = if step < 0 { Affinity::Upstream } else { Affinity::Downstream };
+        range = handler.line_range(probe, affinity);
+    }
+
+    let anchor_y = handler
+        .slice_bounding_box(range.clone())
+        .first()
+        .map(|r| r.y0)
+        .unwrap_or(0.0);
+    let hit = handler.hit_test_point(Point::new(goal, anchor_y));
+    hit.idx.clamp(range.start, range.end)
+}
+
+fn insert_at_caret(handler: &mut dyn TextInputHandler, text: &str) {
+    let range = handler.selected_range();
+    handler.replace_range(range.clone(), text);
+    let caret = range.start + text.len();
+    handler.set_selected_range(caret..caret);
+}
+
+/// Inserts or removes one leading tab from the current logical line, for `Action::Indent` and
+/// `Action::InsertBacktab`.
+fn indent_current_line(handler: &mut dyn TextInputHandler, indent: bool) {
+    let caret = handler.selected_range().end;
+    let line_start = paragraph_range(handler, caret).start;
+    if indent {
+        handler.replace_range(line_start..line_start, "\t");
+        handler.set_selected_range(caret + 1..caret + 1);
+    } else if handler.slice(line_start..handler.len()).starts_with('\t') {
+        handler.replace_range(line_start..line_start + 1, "");
+        handler.set_selected_range(caret.saturating_sub(1)..caret.saturating_sub(1));
+    }
+}
+
+fn transform_word_at_caret(handler: &mut dyn TextInputHandler, f: impl Fn(&str) -> String) {
+    let caret = handler.selected_range().end;
+    let range = word_range(handler, caret);
+    let replaced = f(&handler.slice(range.clone()));
+    handler.replace_range(range.clone(), &replaced);
+    let end = range.start + replaced.len();
+    handler.set_selected_range(end..end);
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) => c.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Toggles the case of the single character before the caret, for `changeCaseOfLetter:`.
+fn swap_letter_case(handler: &mut dyn TextInputHandler) {
+    let caret = handler.selected_range().end;
+    let start = prev_char_boundary(handler, caret);
+    if start == caret {
+        return;
+    }
+    let cluster = handler.slice(start..caret).into_owned();
+    let mut chars = cluster.chars();
+    let c = match chars.next() {
+        Some(c) => c,
+        None => return,
+    };
+    let mut replaced: String = if c.is_uppercase() {
+        c.to_lowercase().collect()
+    } else {
+        c.to_uppercase().collect()
+    };
+    // keep any combining marks that follow the base character in the same cluster
+    replaced.push_str(chars.as_str());
+    handler.replace_range(start..caret, &replaced);
+    let end = start + replaced.len();
+    handler.set_selected_range(end..end);
+}
+
+/// Swaps the character before the caret with the character before that, leaving the caret after
+/// both, for `transpose:`.
+fn transpose_chars(handler: &mut dyn TextInputHandler) {
+    let caret = handler.selected_range().end;
+    let mid = prev_char_boundary(handler, caret);
+    if mid == 0 {
+        return;
+    }
+    let start = prev_char_boundary(handler, mid);
+    let first = handler.slice(start..mid).into_owned();
+    let second = handler.slice(mid..caret).into_owned();
+    let mut swapped = second;
+    swapped.push_str(&first);
+    handler.replace_range(start..caret, &swapped);
+    let end = start + swapped.len();
+    handler.set_selected_range(end..end);
+}
+
+/// Swaps the word before the caret with the word after it, for `transposeWords:`.
+fn transpose_words(handler: &mut dyn TextInputHandler) {
+    let caret = handler.selected_range().end;
+    let before_start = prev_word_boundary(handler, caret);
+    let after_end = next_word_boundary(handler, caret);
+    if before_start == caret || after_end == caret {
+        return;
+    }
+    let before_end = next_word_boundary(handler, before_start);
+    let after_start = prev_word_boundary(handler, after_end);
+    if before_end > after_start {
+        return;
+    }
+    let before = handler.slice(before_start..before_end).into_owned();
+    let between = handler.slice(before_end..after_start).into_owned();
+    let after = handler.slice(after_start..after_end).into_owned();
+    let mut swapped = after;
+    swapped.push_str(&between);
+    swapped.push_str(&before);
+    handler.replace_range(before_start..after_end, &swapped);
+    let end = before_start + swapped.len();
+    handler.set_selected_range(end..end);
+}
+
+/// Turns a mouse gesture (click, drag, double-click, triple-click) into `TextInputHandler`
+/// selection changes, the way most text editors interpret click counts: a single click collapses
+/// the caret to the clicked point, a drag from there extends the selection, a double-click
+/// selects the enclosing word, and a triple-click (or any higher count) selects the enclosing
+/// line.
+///
+/// A click anchors one end of the selection; dragging past the anchor in either direction flips
+/// which end is fixed, the way dragging a selection handle works in every other text editor.
+/// `anchor` and `reversed` track that. Callers own the `TextInputToken` and are responsible for
+/// creating the `TextInputHandler` and sending the `TextInputUpdate::SelectionChanged`
+/// notification; this only computes the range and calls `set_selected_range`.
+#[derive(Debug, Default)]
+pub struct SelectionGesture {
+    anchor: Range<usize>,
+    reversed: bool,
+}
+
+impl SelectionGesture {
+    pub fn new() -> SelectionGesture {
+        SelectionGesture::default()
+    }
+
+    /// Starts a new gesture at a mouse-down. `click_count` is the platform's click count: `0` or
+    /// `1` for a plain click, `2` for a double-click, `3` (or more) for a triple-click and beyond.
+    pub fn mouse_down(&mut self, handler: &mut dyn TextInputHandler, point: Point, click_count: u8) {
+        let offset = handler.hit_test_point(point).idx;
+        self.anchor = match click_count {
+            0 | 1 => offset..offset,
+            2 => word_range(handler, offset),
+            _ => handler.line_range(offset, Affinity::Downstream),
+        };
+        self.reversed = false;
+        handler.set_selected_range(self.anchor.clone());
+    }
+
+    /// Extends the selection towards `point`, for a mouse-drag or the final mouse-up of a
+    /// gesture. Whichever end of the initial anchor the drag has passed becomes the moving end.
+    pub fn extend(&mut self, handler: &mut dyn TextInputHandler, point: Point) {
+        let offset = handler.hit_test_point(point).idx;
+        let range = if offset <= self.anchor.start {
+            self.reversed = true;
+            offset..self.anchor.end
+        } else if offset >= self.anchor.end {
+            self.reversed = false;
+            self.anchor.start..offset
+        } else if self.reversed {
+            offset..self.anchor.end
+        } else {
+            self.anchor.start..offset
+        };
+        handler.set_selected_range(range);
+    }
+
+    /// Starts a shift-click gesture: extends the current selection towards `point` instead of
+    /// starting a new one there, the way a plain `mouse_down` would. The existing selection's
+    /// start becomes the anchor (matching `Action::MoveSelecting`'s convention that the anchor is
+    /// the selection's start and the caret is its end), so a shift-click grows the selection from
+    /// wherever it already started.
+    pub fn extend_from_selection(&mut self, handler: &mut dyn TextInputHandler, point: Point) {
+        let start = handler.selected_range().start;
+        self.anchor = start..start;
+        self.reversed = false;
+        self.extend(handler, point);
+    }
 }
 
 #[allow(dead_code)]
@@ -141,3 +997,371 @@ pub fn simulate_text_input<H: WinHandler + ?Sized>(handler: &mut H, token: Optio
     input_handler.replace_range(selection, &c);
     true
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A minimal `TextInputHandler` backed by a plain `String`, for exercising `handle_action`
+    /// without a real text layout. Lines are newline-delimited and every character is treated as
+    /// one pixel wide, which is enough to exercise display-line vertical motion without a real
+    /// text engine.
+    struct TestDocument {
+        text: String,
+        selection: Range<usize>,
+        goal: Option<f64>,
+        anchor: Option<usize>,
+    }
+
+    impl TestDocument {
+        fn new(text: &str) -> Self {
+            TestDocument {
+                text: text.to_string(),
+                selection: 0..0,
+                goal: None,
+                anchor: None,
+            }
+        }
+    }
+
+    impl TextInputHandler for TestDocument {
+        fn selected_range(&mut self) -> Range<usize> {
+            self.selection.clone()
+        }
+        fn set_selected_range(&mut self, range: Range<usize>) {
+            self.selection = range;
+        }
+        fn composition_range(&mut self) -> Option<Range<usize>> {
+            None
+        }
+        fn set_composition_range(&mut self, _range: Option<Range<usize>>) {}
+        fn is_char_boundary(&mut self, i: usize) -> bool {
+            self.text.is_char_boundary(i)
+        }
+        fn len(&mut self) -> usize {
+            self.text.len()
+        }
+        fn slice<'a>(&'a mut self, range: Range<usize>) -> Cow<'a, str> {
+            self.text[range].into()
+        }
+        fn replace_range(&mut self, range: Range<usize>, text: &str) {
+            self.text.replace_range(range, text);
+        }
+        fn hit_test_point(&mut self, point: Point) -> HitTestPoint {
+            let line_index = point.y.round() as usize;
+            let range = self.line_range(self.line_start_for_index(line_index), Affinity::Downstream);
+            let content_len = if self.text[range.clone()].ends_with('\n') {
+                range.end - range.start - 1
+            } else {
+                range.end - range.start
+            };
+            let column = (point.x.round().max(0.0) as usize).min(content_len);
+            HitTestPoint {
+                idx: range.start + column,
+                is_inside: true,
+            }
+        }
+        // Lines include their trailing newline, so that consecutive lines' ranges are
+        // contiguous and stepping to `range.end` always lands at the start of the next line.
+        // This mock has no bidirectional text, so `affinity` never changes the result.
+        fn line_range(&mut self, char_index: usize, _affinity: Affinity) -> Range<usize> {
+            let start = self.text[..char_index].rfind('\n').map(|i| i + 1).unwrap_or(0);
+            let end = match self.text[char_index..].find('\n') {
+                Some(i) => char_index + i + 1,
+                None => self.text.len(),
+            };
+            start..end
+        }
+        fn bounding_box(&mut self) -> Option<Rect> {
+            None
+        }
+        // This mock has no bidirectional text, so a range is always one visual run.
+        fn slice_bounding_box(&mut self, range: Range<usize>) -> Vec<Rect> {
+            let line_index = self.text[..range.start].matches('\n').count();
+            let line_start = self.line_range(range.start, Affinity::Downstream).start;
+            let x = (range.start - line_start) as f64;
+            vec![Rect::new(x, line_index as f64, x, line_index as f64)]
+        }
+        fn vertical_movement_goal(&mut self) -> Option<f64> {
+            self.goal
+        }
+        fn set_vertical_movement_goal(&mut self, goal: Option<f64>) {
+            self.goal = goal;
+        }
+        fn selection_anchor(&mut self) -> Option<usize> {
+            self.anchor
+        }
+        fn set_selection_anchor(&mut self, anchor: Option<usize>) {
+            self.anchor = anchor;
+        }
+    }
+
+    impl TestDocument {
+        /// Returns the start of the `line_index`-th (zero-based) newline-delimited line, for
+        /// `hit_test_point`.
+        fn line_start_for_index(&self, line_index: usize) -> usize {
+            self.text
+                .match_indices('\n')
+                .nth(line_index.wrapping_sub(1))
+                .map(|(i, _)| i + 1)
+                .unwrap_or(0)
+        }
+    }
+
+    #[test]
+    fn move_word_forward_stops_at_end_of_current_word() {
+        let mut doc = TestDocument::new("hello world");
+        doc.handle_action(Action::Move(Movement::Word(Direction::Downstream)));
+        assert_eq!(doc.selected_range(), 5..5);
+    }
+
+    #[test]
+    fn move_word_forward_from_word_end_skips_the_space_and_consumes_next_word() {
+        let mut doc = TestDocument::new("hello world");
+        doc.set_selected_range(5..5);
+        doc.handle_action(Action::Move(Movement::Word(Direction::Downstream)));
+        assert_eq!(doc.selected_range(), 11..11);
+    }
+
+    #[test]
+    fn move_word_forward_treats_contraction_as_one_word() {
+        let mut doc = TestDocument::new("don't stop");
+        doc.handle_action(Action::Move(Movement::Word(Direction::Downstream)));
+        assert_eq!(doc.selected_range(), 5..5);
+        assert_eq!(&doc.text[..5], "don't");
+    }
+
+    #[test]
+    fn move_word_forward_advances_through_cjk_text() {
+        let mut doc = TestDocument::new("漢字かな");
+        doc.handle_action(Action::Move(Movement::Word(Direction::Downstream)));
+        let boundary = doc.selected_range().end;
+        assert!(boundary > 0 && boundary < doc.text.len());
+        assert!(doc.text.is_char_boundary(boundary));
+    }
+
+    #[test]
+    fn move_word_backward_skips_punctuation_cluster() {
+        let mut doc = TestDocument::new("foo --- bar");
+        doc.set_selected_range(11..11);
+        doc.handle_action(Action::Move(Movement::Word(Direction::Upstream)));
+        assert_eq!(doc.selected_range(), 8..8);
+        // The next step crosses the whole " --- " run at once, landing at the start of "foo".
+        doc.handle_action(Action::Move(Movement::Word(Direction::Upstream)));
+        assert_eq!(doc.selected_range(), 0..0);
+    }
+
+    #[test]
+    fn delete_to_end_of_line_clears_document() {
+        let mut doc = TestDocument::new("hello world");
+        doc.handle_action(Action::Delete(Movement::Line(Direction::Downstream)));
+        assert_eq!(doc.text, "");
+        assert_eq!(doc.selected_range(), 0..0);
+    }
+
+    #[test]
+    fn select_word_selects_word_under_caret() {
+        let mut doc = TestDocument::new("hello world");
+        doc.set_selected_range(7..7);
+        doc.handle_action(Action::SelectWord);
+        assert_eq!(doc.selected_range(), 6..11);
+        assert_eq!(&doc.text[doc.selected_range()], "world");
+    }
+
+    #[test]
+    fn select_word_at_the_trailing_edge_of_a_word_selects_that_word() {
+        let mut doc = TestDocument::new("hello world");
+        doc.set_selected_range(5..5);
+        doc.handle_action(Action::SelectWord);
+        assert_eq!(&doc.text[doc.selected_range()], "hello");
+    }
+
+    #[test]
+    fn select_word_at_the_leading_edge_of_a_word_selects_that_word() {
+        let mut doc = TestDocument::new("hello world");
+        doc.set_selected_range(6..6);
+        doc.handle_action(Action::SelectWord);
+        assert_eq!(&doc.text[doc.selected_range()], "world");
+    }
+
+    #[test]
+    fn transpose_swaps_preceding_characters() {
+        let mut doc = TestDocument::new("ab");
+        doc.set_selected_range(2..2);
+        doc.handle_action(Action::Transpose);
+        assert_eq!(doc.text, "ba");
+    }
+
+    #[test]
+    fn insert_new_line_splits_text_at_caret() {
+        let mut doc = TestDocument::new("hello world");
+        doc.set_selected_range(5..5);
+        doc.handle_action(Action::InsertNewLine {
+            ignore_autocomplete: false,
+        });
+        assert_eq!(doc.text, "hello\n world");
+        assert_eq!(doc.selected_range(), 6..6);
+    }
+
+    #[test]
+    fn display_line_down_sticks_to_goal_column_through_a_shorter_line() {
+        let mut doc = TestDocument::new("ab\nx\nabcdef");
+        doc.set_selected_range(2..2);
+        doc.handle_action(Action::Move(Movement::Vertical(VerticalMovement::DisplayLineDown)));
+        // "x" is too short for column 2, so the caret lands at its end...
+        assert_eq!(doc.selected_range(), 4..4);
+        doc.handle_action(Action::Move(Movement::Vertical(VerticalMovement::DisplayLineDown)));
+        // ...but the next line down returns to column 2, not column 1.
+        assert_eq!(doc.selected_range(), 7..7);
+    }
+
+    #[test]
+    fn horizontal_movement_resets_the_goal_column() {
+        let mut doc = TestDocument::new("ab\nx\nabcdef");
+        doc.set_selected_range(2..2);
+        doc.handle_action(Action::Move(Movement::Vertical(VerticalMovement::DisplayLineDown)));
+        assert_eq!(doc.selected_range(), 4..4);
+        doc.handle_action(Action::Move(Movement::Grapheme(Direction::Left)));
+        doc.handle_action(Action::Move(Movement::Vertical(VerticalMovement::DisplayLineDown)));
+        // The goal column was cleared by the grapheme move, so it's re-derived from the new
+        // (one column earlier) caret position instead of sticking to the original column 2.
+        assert_eq!(doc.selected_range(), 5..5);
+    }
+
+    #[test]
+    fn move_grapheme_forward_treats_combining_mark_as_one_character() {
+        // "e" followed by a combining acute accent is one grapheme cluster.
+        let mut doc = TestDocument::new("e\u{0301}fg");
+        doc.handle_action(Action::Move(Movement::Grapheme(Direction::Downstream)));
+        assert_eq!(doc.selected_range(), "e\u{0301}".len().."e\u{0301}".len());
+    }
+
+    #[test]
+    fn move_grapheme_backward_treats_combining_mark_as_one_character() {
+        let mut doc = TestDocument::new("e\u{0301}fg");
+        doc.set_selected_range("e\u{0301}".len().."e\u{0301}".len());
+        doc.handle_action(Action::Move(Movement::Grapheme(Direction::Upstream)));
+        assert_eq!(doc.selected_range(), 0..0);
+    }
+
+    #[test]
+    fn next_grapheme_offset_is_none_at_end_of_document() {
+        let mut doc = TestDocument::new("ab");
+        assert_eq!(doc.next_grapheme_offset(2), None);
+    }
+
+    #[test]
+    fn previous_grapheme_offset_is_none_at_start_of_document() {
+        let mut doc = TestDocument::new("ab");
+        assert_eq!(doc.previous_grapheme_offset(0), None);
+    }
+
+    #[test]
+    fn next_grapheme_offset_widens_the_scan_window_for_a_long_cluster() {
+        // A base character followed by far more combining marks than fit in one scan window, to
+        // exercise the widen-and-retry path.
+        let mut text = String::from("a");
+        for _ in 0..(GRAPHEME_SCAN_WINDOW * 3) {
+            text.push('\u{0301}');
+        }
+        text.push('b');
+        let len = text.len();
+        let mut doc = TestDocument::new(&text);
+        assert_eq!(doc.next_grapheme_offset(0), Some(len - 1));
+        assert_eq!(doc.previous_grapheme_offset(len - 1), Some(0));
+    }
+
+    #[test]
+    fn next_word_offset_is_none_at_end_of_document() {
+        let mut doc = TestDocument::new("hi");
+        assert_eq!(doc.next_word_offset(2), None);
+    }
+
+    #[test]
+    fn previous_word_offset_is_none_at_start_of_document() {
+        let mut doc = TestDocument::new("hi");
+        assert_eq!(doc.previous_word_offset(0), None);
+    }
+
+    #[test]
+    fn single_click_collapses_the_caret_to_the_clicked_point() {
+        let mut doc = TestDocument::new("hello world");
+        let mut gesture = SelectionGesture::new();
+        gesture.mouse_down(&mut doc, Point::new(7.0, 0.0), 1);
+        assert_eq!(doc.selected_range(), 7..7);
+    }
+
+    #[test]
+    fn dragging_after_a_click_extends_the_selection_to_the_anchor() {
+        let mut doc = TestDocument::new("hello world");
+        let mut gesture = SelectionGesture::new();
+        gesture.mouse_down(&mut doc, Point::new(2.0, 0.0), 1);
+        gesture.extend(&mut doc, Point::new(7.0, 0.0));
+        assert_eq!(doc.selected_range(), 2..7);
+    }
+
+    #[test]
+    fn dragging_past_the_anchor_reverses_the_selection() {
+        let mut doc = TestDocument::new("hello world");
+        let mut gesture = SelectionGesture::new();
+        gesture.mouse_down(&mut doc, Point::new(7.0, 0.0), 1);
+        gesture.extend(&mut doc, Point::new(2.0, 0.0));
+        assert_eq!(doc.selected_range(), 2..7);
+        // Dragging back past the original anchor again flips the selection back around it.
+        gesture.extend(&mut doc, Point::new(9.0, 0.0));
+        assert_eq!(doc.selected_range(), 7..9);
+    }
+
+    #[test]
+    fn double_click_selects_the_enclosing_word() {
+        let mut doc = TestDocument::new("hello world");
+        let mut gesture = SelectionGesture::new();
+        gesture.mouse_down(&mut doc, Point::new(7.0, 0.0), 2);
+        assert_eq!(doc.selected_range(), 6..11);
+    }
+
+    #[test]
+    fn triple_click_selects_the_enclosing_line() {
+        let mut doc = TestDocument::new("ab\nhello world\ncd");
+        let mut gesture = SelectionGesture::new();
+        gesture.mouse_down(&mut doc, Point::new(7.0, 1.0), 3);
+        assert_eq!(doc.selected_range(), 3..15);
+    }
+
+    #[test]
+    fn move_selecting_grows_the_selection_from_a_fixed_anchor() {
+        let mut doc = TestDocument::new("hello");
+        doc.set_selected_range(5..5);
+        doc.handle_action(Action::MoveSelecting(Movement::Grapheme(Direction::Left)));
+        assert_eq!(doc.selected_range(), 4..5);
+        doc.handle_action(Action::MoveSelecting(Movement::Grapheme(Direction::Left)));
+        assert_eq!(doc.selected_range(), 3..5);
+    }
+
+    #[test]
+    fn move_selecting_past_the_anchor_reverses_the_selection() {
+        let mut doc = TestDocument::new("hello world");
+        doc.set_selected_range(5..5);
+        doc.handle_action(Action::MoveSelecting(Movement::Grapheme(Direction::Right)));
+        assert_eq!(doc.selected_range(), 5..6);
+        doc.handle_action(Action::MoveSelecting(Movement::Grapheme(Direction::Left)));
+        assert_eq!(doc.selected_range(), 5..5);
+        // One more step pushes the active edge past the anchor at 5, so the selection should
+        // extend on the other side of it instead of collapsing again.
+        doc.handle_action(Action::MoveSelecting(Movement::Grapheme(Direction::Left)));
+        assert_eq!(doc.selected_range(), 4..5);
+    }
+
+    #[test]
+    fn move_selecting_after_an_unrelated_selection_change_starts_a_fresh_anchor() {
+        let mut doc = TestDocument::new("hello world");
+        doc.set_selected_range(5..5);
+        doc.handle_action(Action::MoveSelecting(Movement::Grapheme(Direction::Left)));
+        assert_eq!(doc.selected_range(), 4..5);
+        // Something other than `Action::MoveSelecting` (e.g. a click) jumps the caret elsewhere,
+        // discarding the old anchor at 5.
+        doc.set_selected_range(8..8);
+        doc.handle_action(Action::MoveSelecting(Movement::Grapheme(Direction::Left)));
+        assert_eq!(doc.selected_range(), 7..8);
+    }
+}